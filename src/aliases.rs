@@ -0,0 +1,57 @@
+use crate::format;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct NameSeen {
+  pub name: String,
+  pub first_seen: i64,
+  pub last_seen: i64,
+}
+
+pub fn add_name(names: &mut Vec<NameSeen>, name: &str, seen: i64) {
+  match names.iter_mut().find(|name_seen| name_seen.name == name) {
+    Some(name_seen) => {
+      name_seen.first_seen = name_seen.first_seen.min(seen);
+      name_seen.last_seen = name_seen.last_seen.max(seen);
+    }
+    None => names.push(NameSeen {
+      name: String::from(name),
+      first_seen: seen,
+      last_seen: seen,
+    }),
+  }
+}
+
+pub fn merge_names(names: &mut Vec<NameSeen>, other_names: &Vec<NameSeen>) {
+  for other in other_names {
+    add_name(names, &other.name, other.first_seen);
+    add_name(names, &other.name, other.last_seen);
+  }
+  names.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+}
+
+// every name other than the current one, most recently used first
+pub fn get_aliases<'a>(names: &'a Vec<NameSeen>, current_name: &str) -> Vec<&'a NameSeen> {
+  let mut aliases: Vec<&NameSeen> = names
+    .iter()
+    .filter(|name_seen| name_seen.name != current_name)
+    .collect();
+  aliases.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+  return aliases;
+}
+
+pub fn format_aliases(names: &Vec<NameSeen>, current_name: &str) -> String {
+  let aliases: Vec<String> = get_aliases(names, current_name)
+    .iter()
+    .map(|name_seen| {
+      format!(
+        "{} until {}",
+        name_seen.name,
+        &format::timestamp_to_date(name_seen.last_seen)[..10]
+      )
+    })
+    .collect();
+  if aliases.len() == 0 {
+    return String::default();
+  }
+  return format!(" (aka {})", aliases.join(", "));
+}
@@ -0,0 +1,144 @@
+use crate::error::RuntimeError;
+
+// positional args plus `--name value` or `--name=value` options, in any order. flags are options
+// that are on without a value, e.g. `--ranked`, and only take the next arg as their value when it
+// is true or false, so that `--ranked SomePlayer` keeps SomePlayer as a positional arg
+pub struct Args {
+  pub positional: Vec<String>,
+  options: std::collections::HashMap<String, String>,
+}
+
+impl Args {
+  pub fn parse(args: &[String], flags: &[&str]) -> Args {
+    let mut positional: Vec<String> = vec![];
+    let mut options = std::collections::HashMap::new();
+    let mut i = 0;
+    while i < args.len() {
+      let arg = &args[i];
+      if arg.starts_with("--") {
+        let option = arg.trim_start_matches("--");
+        if let Some(separator) = option.find('=') {
+          options.insert(
+            String::from(&option[..separator]),
+            String::from(&option[separator + 1..]),
+          );
+          i += 1;
+          continue;
+        }
+        let is_flag = flags.contains(&option);
+        let value = match args.get(i + 1) {
+          Some(value) if is_flag && (value == "true" || value == "false") => {
+            i += 1;
+            value.clone()
+          }
+          Some(value) if !is_flag && !value.starts_with("--") => {
+            i += 1;
+            value.clone()
+          }
+          _ => String::from("true"),
+        };
+        options.insert(String::from(option), value);
+      } else {
+        positional.push(arg.clone());
+      }
+      i += 1;
+    }
+    Args {
+      positional,
+      options,
+    }
+  }
+  pub fn get(&self, name: &str) -> Option<&str> {
+    self.options.get(name).map(|value| value.as_str())
+  }
+  // a flag given without a value is on
+  pub fn get_bool(&self, name: &str) -> Result<bool, RuntimeError> {
+    match self.get(name) {
+      None | Some("false") => Ok(false),
+      Some("true") => Ok(true),
+      Some(value) => Err(RuntimeError::new(&format!(
+        "Expected true or false for --{}, got '{}'",
+        name, value
+      ))),
+    }
+  }
+  pub fn get_i32(&self, name: &str) -> Result<Option<i32>, RuntimeError> {
+    match self.get(name) {
+      None => Ok(None),
+      Some(value) => match value.parse() {
+        Ok(v) => Ok(Some(v)),
+        Err(_) => Err(RuntimeError::new(&format!(
+          "Expected a number for --{}, got '{}'",
+          name, value
+        ))),
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const FLAGS: [&str; 2] = ["ranked", "watch"];
+
+  fn parse(args: &[&str]) -> Args {
+    let args: Vec<String> = args.iter().map(|arg| String::from(*arg)).collect();
+    Args::parse(&args, &FLAGS)
+  }
+
+  #[test]
+  fn options_take_the_next_arg() {
+    let args = parse(&["Hera", "--since", "2020-05-01", "team", "--last", "10"]);
+    assert_eq!(args.positional, vec!["Hera", "team"]);
+    assert_eq!(args.get("since"), Some("2020-05-01"));
+    assert_eq!(args.get_i32("last").unwrap(), Some(10));
+    assert_eq!(args.get("until"), None);
+  }
+
+  #[test]
+  fn options_can_use_an_equals_sign() {
+    let args = parse(&[
+      "--map=arabia",
+      "--ranked=false",
+      "Hera",
+      "--clan-patterns=[],|",
+    ]);
+    assert_eq!(args.positional, vec!["Hera"]);
+    assert_eq!(args.get("map"), Some("arabia"));
+    assert!(!args.get_bool("ranked").unwrap());
+    assert_eq!(args.get("clan-patterns"), Some("[],|"));
+  }
+
+  #[test]
+  fn flags_do_not_take_the_next_arg() {
+    let args = parse(&["--ranked", "Hera", "--watch", "team"]);
+    assert_eq!(args.positional, vec!["Hera", "team"]);
+    assert!(args.get_bool("ranked").unwrap());
+    assert!(args.get_bool("watch").unwrap());
+  }
+
+  #[test]
+  fn flags_take_true_or_false() {
+    let args = parse(&["--ranked", "false", "Hera", "--watch", "true"]);
+    assert_eq!(args.positional, vec!["Hera"]);
+    assert!(!args.get_bool("ranked").unwrap());
+    assert!(args.get_bool("watch").unwrap());
+    assert!(!parse(&["Hera"]).get_bool("ranked").unwrap());
+  }
+
+  #[test]
+  fn options_without_a_value_are_true() {
+    let args = parse(&["Hera", "--since", "--ranked"]);
+    assert_eq!(args.positional, vec!["Hera"]);
+    assert_eq!(args.get("since"), Some("true"));
+    assert!(args.get_bool("ranked").unwrap());
+    assert_eq!(parse(&["Hera", "--last"]).get("last"), Some("true"));
+  }
+
+  #[test]
+  fn rejects_values_of_the_wrong_type() {
+    assert!(parse(&["--ranked=yes"]).get_bool("ranked").is_err());
+    assert!(parse(&["--last", "ten"]).get_i32("last").is_err());
+  }
+}
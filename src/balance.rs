@@ -0,0 +1,262 @@
+use crate::batch;
+use crate::error::RuntimeError;
+use crate::player_tracker;
+use crate::rating;
+
+pub const MIN_PLAYERS: usize = 4;
+pub const DEFAULT_COUNT: usize = 3;
+// pairs need this many games against or with each other before their history counts
+const MIN_PAIR_GAMES: i32 = 3;
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct BalanceRequest {
+  pub players: Vec<batch::BatchPlayer>,
+  pub leaderboard: Option<String>,
+  pub concurrency: Option<usize>,
+  pub rival_penalty: Option<f64>,
+  pub teammate_bonus: Option<f64>,
+  pub count: Option<usize>,
+}
+
+// both weights are in rating points and default to 0, which balances on ratings alone
+#[derive(Debug, Clone, Copy)]
+pub struct BalanceOptions {
+  // added for every pair of frequent opponents put on the same team
+  pub rival_penalty: f64,
+  // subtracted for a pair that wins together, scaled by how much more they win than lose
+  pub teammate_bonus: f64,
+  pub count: usize,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct BalancePlayer {
+  pub profile_id: i32,
+  pub name: String,
+  pub rating: i32,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct TeamSplit {
+  pub first_team: Vec<BalancePlayer>,
+  pub second_team: Vec<BalancePlayer>,
+  pub first_team_rating: f64,
+  pub second_team_rating: f64,
+  // the chance of the first team winning from the average ratings
+  pub first_team_win_probability: f64,
+  // the history adjustment from rivals and teammates, in rating points
+  pub history_adjustment: f64,
+  pub score: f64,
+}
+
+impl TeamSplit {
+  pub fn format(&self) -> String {
+    let names = |team: &Vec<BalancePlayer>| {
+      team
+        .iter()
+        .map(|player| format!("{} ({})", player.name, player.rating))
+        .collect::<Vec<String>>()
+        .join(", ")
+    };
+    format!(
+      "{:.0} vs {:.0} ({:.0}% / {:.0}%), history {:+.0}\n  {}\n  {}",
+      self.first_team_rating,
+      self.second_team_rating,
+      self.first_team_win_probability * 100.0,
+      (1.0 - self.first_team_win_probability) * 100.0,
+      self.history_adjustment,
+      names(&self.first_team),
+      names(&self.second_team)
+    )
+  }
+}
+
+fn average_rating(players: &Vec<&BalancePlayer>) -> f64 {
+  players
+    .iter()
+    .map(|player| player.rating as f64)
+    .sum::<f64>()
+    / players.len() as f64
+}
+
+// the history between two players who would be on the same team, read from the first one's
+// tracker
+fn pair_adjustment(
+  tracker: &player_tracker::PlayerTracker,
+  other_profile_id: i32,
+  options: &BalanceOptions,
+) -> f64 {
+  let mut adjustment = 0.0;
+  let games_against = tracker
+    .records
+    .get(&other_profile_id)
+    .map_or(0, |record| record.games.len() as i32);
+  if games_against >= MIN_PAIR_GAMES {
+    adjustment += options.rival_penalty;
+  }
+  let (wins_with, losses_with) = tracker.get_teammate_record(other_profile_id);
+  let games_with = wins_with + losses_with;
+  if games_with >= MIN_PAIR_GAMES {
+    adjustment -= options.teammate_bonus * (wins_with - losses_with) as f64 / games_with as f64;
+  }
+  return adjustment;
+}
+
+fn team_adjustment(
+  team: &Vec<usize>,
+  trackers: &Vec<&player_tracker::PlayerTracker>,
+  options: &BalanceOptions,
+) -> f64 {
+  let mut adjustment = 0.0;
+  for i in 0..team.len() {
+    for j in (i + 1)..team.len() {
+      adjustment += pair_adjustment(trackers[team[i]], trackers[team[j]].profile_id, options);
+    }
+  }
+  return adjustment;
+}
+
+// every split into two teams is scored by the rating difference plus the history adjustment, and
+// the lowest scores are returned. the first player is always on the first team so that each split
+// is only counted once
+pub fn balance(
+  players: &Vec<BalancePlayer>,
+  trackers: &Vec<&player_tracker::PlayerTracker>,
+  options: &BalanceOptions,
+) -> Vec<TeamSplit> {
+  let n = players.len();
+  // the first team is the bigger one when the lobby has an odd number of players
+  let first_team_size = n - n / 2;
+  let mut splits: Vec<TeamSplit> = vec![];
+  for mask in 0..(1u32 << n) {
+    if mask & 1 == 0 || mask.count_ones() as usize != first_team_size {
+      continue;
+    }
+    let first: Vec<usize> = (0..n).filter(|i| mask & (1 << i) != 0).collect();
+    let second: Vec<usize> = (0..n).filter(|i| mask & (1 << i) == 0).collect();
+    let first_players: Vec<&BalancePlayer> = first.iter().map(|i| &players[*i]).collect();
+    let second_players: Vec<&BalancePlayer> = second.iter().map(|i| &players[*i]).collect();
+    let first_team_rating = average_rating(&first_players);
+    let second_team_rating = average_rating(&second_players);
+    let history_adjustment =
+      team_adjustment(&first, trackers, options) + team_adjustment(&second, trackers, options);
+    splits.push(TeamSplit {
+      first_team: first_players.into_iter().cloned().collect(),
+      second_team: second_players.into_iter().cloned().collect(),
+      first_team_rating,
+      second_team_rating,
+      first_team_win_probability: rating::expected_score(first_team_rating, second_team_rating),
+      history_adjustment,
+      score: (first_team_rating - second_team_rating).abs() + history_adjustment,
+    });
+  }
+  splits.sort_by(|a, b| {
+    a.score
+      .partial_cmp(&b.score)
+      .unwrap_or(std::cmp::Ordering::Equal)
+  });
+  splits.truncate(options.count);
+  return splits;
+}
+
+pub fn to_balance_players(
+  summaries: &Vec<&batch::PlayerSummary>,
+) -> Result<Vec<BalancePlayer>, RuntimeError> {
+  let mut ret: Vec<BalancePlayer> = vec![];
+  for summary in summaries {
+    match summary.rating {
+      Some(rating) => ret.push(BalancePlayer {
+        profile_id: summary.profile_id,
+        name: summary.name.clone(),
+        rating,
+      }),
+      None => {
+        return Err(RuntimeError::new(&format!(
+          "No rating found for {}",
+          summary.name
+        )))
+      }
+    }
+  }
+  Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn players(ratings: &[i32]) -> Vec<BalancePlayer> {
+    ratings
+      .iter()
+      .enumerate()
+      .map(|(i, rating)| BalancePlayer {
+        profile_id: i as i32 + 1,
+        name: format!("player{}", i + 1),
+        rating: *rating,
+      })
+      .collect()
+  }
+
+  fn options(count: usize) -> BalanceOptions {
+    BalanceOptions {
+      rival_penalty: 0.0,
+      teammate_bonus: 0.0,
+      count,
+    }
+  }
+
+  fn profile_ids(team: &[BalancePlayer]) -> Vec<i32> {
+    team.iter().map(|player| player.profile_id).collect()
+  }
+
+  #[test]
+  fn counts_each_split_once() {
+    let players = players(&[1000, 1100, 1200, 1300]);
+    let trackers: Vec<player_tracker::PlayerTracker> = players
+      .iter()
+      .map(|player| player_tracker::PlayerTracker::new(player.profile_id))
+      .collect();
+    let splits = balance(&players, &trackers.iter().collect(), &options(10));
+    // 1 with 2, 1 with 3 and 1 with 4, and never their mirror images
+    assert_eq!(splits.len(), 3);
+    for split in &splits {
+      assert_eq!(split.first_team[0].profile_id, 1);
+      assert_eq!(split.second_team.len(), 2);
+    }
+    assert_eq!(profile_ids(&splits[0].first_team), vec![1, 4]);
+    assert_eq!(profile_ids(&splits[0].second_team), vec![2, 3]);
+    assert_eq!(splits[0].score, 0.0);
+    assert_eq!(splits[0].first_team_win_probability, 0.5);
+  }
+
+  #[test]
+  fn puts_the_extra_player_on_the_first_team() {
+    let players = players(&[1200, 1000, 1100, 1300, 900]);
+    let trackers: Vec<player_tracker::PlayerTracker> = players
+      .iter()
+      .map(|player| player_tracker::PlayerTracker::new(player.profile_id))
+      .collect();
+    let splits = balance(&players, &trackers.iter().collect(), &options(10));
+    assert_eq!(splits.len(), 6);
+    for split in &splits {
+      assert_eq!(split.first_team.len(), 3);
+      assert_eq!(split.second_team.len(), 2);
+    }
+    assert_eq!(profile_ids(&splits[0].first_team), vec![1, 2, 3]);
+    assert_eq!(profile_ids(&splits[0].second_team), vec![4, 5]);
+    assert_eq!(splits[0].first_team_rating, 1100.0);
+    assert_eq!(splits[0].second_team_rating, 1100.0);
+    assert!((splits[1].score - 250.0 / 3.0).abs() < 1e-9);
+  }
+
+  #[test]
+  fn keeps_the_best_splits() {
+    let players = players(&[1200, 1000, 1100, 1300, 900]);
+    let trackers: Vec<player_tracker::PlayerTracker> = players
+      .iter()
+      .map(|player| player_tracker::PlayerTracker::new(player.profile_id))
+      .collect();
+    let splits = balance(&players, &trackers.iter().collect(), &options(2));
+    assert_eq!(splits.len(), 2);
+    assert_eq!(splits[0].score, 0.0);
+  }
+}
@@ -0,0 +1,193 @@
+use crate::fetch;
+use crate::player_tracker;
+use crate::rating;
+
+pub const DEFAULT_CONCURRENCY: usize = 4;
+pub const MAX_CONCURRENCY: usize = 8;
+// a full 4v4 lobby
+pub const MAX_PLAYERS: usize = 8;
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum BatchPlayer {
+  ProfileId(i32),
+  Name(String),
+}
+
+impl BatchPlayer {
+  // numbers on the command line are taken as profile ids
+  pub fn parse(arg: &str) -> BatchPlayer {
+    match arg.parse::<i32>() {
+      Ok(profile_id) => BatchPlayer::ProfileId(profile_id),
+      Err(_) => BatchPlayer::Name(String::from(arg)),
+    }
+  }
+  pub fn describe(&self) -> String {
+    match self {
+      BatchPlayer::ProfileId(profile_id) => profile_id.to_string(),
+      BatchPlayer::Name(name) => name.clone(),
+    }
+  }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct BatchRequest {
+  pub players: Vec<BatchPlayer>,
+  pub leaderboard: Option<String>,
+  pub concurrency: Option<usize>,
+}
+
+pub fn get_concurrency(concurrency: Option<usize>) -> usize {
+  concurrency
+    .unwrap_or(DEFAULT_CONCURRENCY)
+    .max(1)
+    .min(MAX_CONCURRENCY)
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct PlayerSummary {
+  pub profile_id: i32,
+  pub name: String,
+  // the rating in the most recent game
+  pub rating: Option<i32>,
+  pub games: i32,
+  pub wins: i32,
+  pub losses: i32,
+  pub performance: rating::Performance,
+  pub last_played: i64,
+}
+
+impl PlayerSummary {
+  pub fn new(
+    match_history: &Vec<fetch::MatchHistoryGameResponse>,
+    tracker: &player_tracker::PlayerTracker,
+  ) -> PlayerSummary {
+    let me = match_history
+      .get(0)
+      .and_then(|game| game.get_player_by_profile_id(tracker.profile_id));
+    PlayerSummary {
+      profile_id: tracker.profile_id,
+      name: me.map_or(tracker.profile_id.to_string(), |player| player.get_name()),
+      rating: me.and_then(|player| player.rating),
+      games: match_history.len() as i32,
+      wins: tracker.wins,
+      losses: tracker.losses,
+      performance: tracker.performance.clone(),
+      last_played: match_history.get(0).map_or(0, |game| game.started),
+    }
+  }
+  pub fn format(&self) -> String {
+    format!(
+      "{} (elo {}): {} games, {} wins, {} losses, vs expectation {}",
+      self.name,
+      self
+        .rating
+        .map_or(String::from("unknown"), |rating| rating.to_string()),
+      self.games,
+      self.wins,
+      self.losses,
+      self.performance.format()
+    )
+  }
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct BatchPlayerResult {
+  pub query: String,
+  pub summary: Option<PlayerSummary>,
+  pub error: Option<String>,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct PairwiseHistory {
+  pub first_profile_id: i32,
+  pub second_profile_id: i32,
+  pub games_against: i32,
+  pub first_wins_against: i32,
+  pub first_losses_to: i32,
+  pub wins_together: i32,
+  pub losses_together: i32,
+}
+
+impl PairwiseHistory {
+  pub fn has_history(&self) -> bool {
+    self.games_against + self.wins_together + self.losses_together > 0
+  }
+  pub fn format(&self, first_name: &str, second_name: &str) -> String {
+    format!(
+      "{} vs {}: {} games against ({}-{}), {}-{} together",
+      first_name,
+      second_name,
+      self.games_against,
+      self.first_wins_against,
+      self.first_losses_to,
+      self.wins_together,
+      self.losses_together
+    )
+  }
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct BatchResponse {
+  pub players: Vec<BatchPlayerResult>,
+  pub pairs: Vec<PairwiseHistory>,
+}
+
+impl BatchResponse {
+  fn get_name(&self, profile_id: i32) -> String {
+    self
+      .players
+      .iter()
+      .filter_map(|result| result.summary.as_ref())
+      .find(|summary| summary.profile_id == profile_id)
+      .map_or(profile_id.to_string(), |summary| summary.name.clone())
+  }
+  pub fn format(&self) -> String {
+    let mut ret = String::from("Players:\n");
+    for result in &self.players {
+      match (&result.summary, &result.error) {
+        (Some(summary), _) => ret += &format!("  {}\n", summary.format()),
+        (None, Some(error)) => ret += &format!("  {}: {}\n", result.query, error),
+        (None, None) => (),
+      }
+    }
+    ret += "Pairwise history:\n";
+    for pair in self.pairs.iter().filter(|pair| pair.has_history()) {
+      ret += &format!(
+        "  {}\n",
+        pair.format(
+          &self.get_name(pair.first_profile_id),
+          &self.get_name(pair.second_profile_id)
+        )
+      );
+    }
+    return ret;
+  }
+}
+
+// every pair is read from the first player's history, which holds all the games the two played
+// unless a filter cut some of them off
+pub fn pairwise_histories(trackers: &Vec<&player_tracker::PlayerTracker>) -> Vec<PairwiseHistory> {
+  let mut ret: Vec<PairwiseHistory> = vec![];
+  for i in 0..trackers.len() {
+    for j in (i + 1)..trackers.len() {
+      let first = trackers[i];
+      let second_profile_id = trackers[j].profile_id;
+      let (first_wins_against, first_losses_to) = first.get_win_loss_record(second_profile_id);
+      let (wins_together, losses_together) = first.get_teammate_record(second_profile_id);
+      ret.push(PairwiseHistory {
+        first_profile_id: first.profile_id,
+        second_profile_id,
+        games_against: first
+          .records
+          .get(&second_profile_id)
+          .map_or(0, |record| record.games.len() as i32),
+        first_wins_against,
+        first_losses_to,
+        wins_together,
+        losses_together,
+      });
+    }
+  }
+  return ret;
+}
@@ -0,0 +1,417 @@
+use crate::aliases;
+use crate::error::RuntimeError;
+use crate::fetch;
+use crate::player_tracker;
+
+// "[KM] name", "(KM) name", "{KM} name", "<KM> name" and "KM|name"
+pub const DEFAULT_PATTERNS: &str = "[],(),{},<>,|";
+
+// longer prefixes are more likely part of the name than a clan tag
+const MAX_TAG_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClanPattern {
+  // the tag is enclosed in these characters at the start of the name
+  Enclosed(char, char),
+  // the tag comes before this character at the start of the name
+  Separator(char),
+}
+
+// patterns are separated by commas, each is either an opening and closing character or a
+// single separator character
+pub fn parse_patterns(patterns: &str) -> Result<Vec<ClanPattern>, RuntimeError> {
+  let mut ret: Vec<ClanPattern> = vec![];
+  for pattern in patterns.split(',') {
+    let chars: Vec<char> = pattern.trim().chars().collect();
+    match chars.len() {
+      1 => ret.push(ClanPattern::Separator(chars[0])),
+      2 => ret.push(ClanPattern::Enclosed(chars[0], chars[1])),
+      _ => {
+        return Err(RuntimeError::new(&format!(
+          "Invalid clan tag pattern '{}', expected two enclosing characters like [] or a separator like |",
+          pattern
+        )))
+      }
+    }
+  }
+  Ok(ret)
+}
+
+fn is_valid_tag(tag: &str, rest: &str) -> bool {
+  tag.len() > 0 && tag.chars().count() <= MAX_TAG_LEN && rest.trim().len() > 0
+}
+
+pub fn extract_clan_tag(name: &str, patterns: &Vec<ClanPattern>) -> Option<String> {
+  let name = name.trim();
+  for pattern in patterns {
+    let (tag, rest) = match *pattern {
+      ClanPattern::Enclosed(open, close) => {
+        if !name.starts_with(open) {
+          continue;
+        }
+        let inner = &name[open.len_utf8()..];
+        match inner.find(close) {
+          Some(end) => (&inner[..end], &inner[end + close.len_utf8()..]),
+          None => continue,
+        }
+      }
+      ClanPattern::Separator(separator) => match name.find(separator) {
+        Some(end) => (&name[..end], &name[end + separator.len_utf8()..]),
+        None => continue,
+      },
+    };
+    let tag = tag.trim();
+    if is_valid_tag(tag, rest) {
+      return Some(String::from(tag));
+    }
+  }
+  None
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ClanRecord {
+  pub tag: String,
+  pub wins_against: i32,
+  pub losses_to: i32,
+  pub wins_with: i32,
+  pub losses_with: i32,
+  pub players: Vec<String>,
+}
+
+impl ClanRecord {
+  fn new(tag: &str) -> ClanRecord {
+    ClanRecord {
+      tag: String::from(tag),
+      wins_against: 0,
+      losses_to: 0,
+      wins_with: 0,
+      losses_with: 0,
+      players: vec![],
+    }
+  }
+  fn get_games(&self) -> i32 {
+    self.wins_against + self.losses_to + self.wins_with + self.losses_with
+  }
+  fn add_player(&mut self, name: String) {
+    if !self.players.contains(&name) {
+      self.players.push(name);
+    }
+  }
+  pub fn format(&self) -> String {
+    let mut parts: Vec<String> = vec![];
+    if self.wins_against + self.losses_to > 0 {
+      parts.push(format!(
+        "wins against {}, losses to {}",
+        self.wins_against, self.losses_to
+      ));
+    }
+    if self.wins_with + self.losses_with > 0 {
+      parts.push(format!(
+        "wins with {}, losses with {}",
+        self.wins_with, self.losses_with
+      ));
+    }
+    return format!(
+      "[{}] {} ({})",
+      self.tag,
+      parts.join(", "),
+      self.players.join(", ")
+    );
+  }
+}
+
+fn get_clan_record<'a>(records: &'a mut Vec<ClanRecord>, tag: &str) -> &'a mut ClanRecord {
+  match records.iter().position(|record| record.tag == tag) {
+    Some(i) => &mut records[i],
+    None => {
+      records.push(ClanRecord::new(tag));
+      records.last_mut().unwrap()
+    }
+  }
+}
+
+// the tag is read from the name each player had in that game, so players who changed clans
+// count towards the clan they were in at the time
+pub fn analyze(
+  match_history: &Vec<fetch::MatchHistoryGameResponse>,
+  tracker: &player_tracker::PlayerTracker,
+  patterns: &Vec<ClanPattern>,
+) -> Vec<ClanRecord> {
+  let mut records: Vec<ClanRecord> = vec![];
+  for game in match_history {
+    let is_win = match tracker
+      .get_outcome(game.get_match_id())
+      .and_then(|outcome| outcome.is_win)
+    {
+      Some(is_win) => is_win,
+      None => continue,
+    };
+    // a clan counts once per game however many of its players were in it
+    let mut enemy_tags: Vec<(String, bool)> = vec![];
+    for enemy_player in game.get_opposing_team(tracker.profile_id) {
+      let tag = match extract_clan_tag(&enemy_player.get_name(), patterns) {
+        Some(tag) => tag,
+        None => continue,
+      };
+      let is_win_against = match game.get_result_against(is_win, enemy_player) {
+        Some(is_win_against) => is_win_against,
+        None => continue,
+      };
+      get_clan_record(&mut records, &tag).add_player(enemy_player.get_name());
+      if !enemy_tags.iter().any(|(enemy_tag, _)| *enemy_tag == tag) {
+        enemy_tags.push((tag, is_win_against));
+      }
+    }
+    for (tag, is_win_against) in enemy_tags {
+      let record = get_clan_record(&mut records, &tag);
+      if is_win_against {
+        record.wins_against += 1;
+      } else {
+        record.losses_to += 1;
+      }
+    }
+    let mut ally_tags: Vec<String> = vec![];
+    for ally_player in game.get_my_team(tracker.profile_id) {
+      let tag = match extract_clan_tag(&ally_player.get_name(), patterns) {
+        Some(tag) => tag,
+        None => continue,
+      };
+      get_clan_record(&mut records, &tag).add_player(ally_player.get_name());
+      if !ally_tags.contains(&tag) {
+        ally_tags.push(tag);
+      }
+    }
+    for tag in ally_tags {
+      let record = get_clan_record(&mut records, &tag);
+      if is_win {
+        record.wins_with += 1;
+      } else {
+        record.losses_with += 1;
+      }
+    }
+  }
+  records.sort_by(|a, b| b.get_games().cmp(&a.get_games()));
+  return records;
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct RosterMember {
+  pub profile_id: i32,
+  pub name: String,
+  pub last_seen: i64,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ClanRoster {
+  pub tag: String,
+  // players whose latest known name carries the tag
+  pub members: Vec<RosterMember>,
+  // players who carried the tag under an earlier name
+  pub former_members: Vec<RosterMember>,
+}
+
+impl ClanRoster {
+  pub fn format(&self) -> String {
+    let mut ret = format!("[{}] {} members\n", self.tag, self.members.len());
+    for member in &self.members {
+      ret += &format!(
+        "  {} (last seen {})\n",
+        member.name,
+        crate::format::timestamp_to_date(member.last_seen)
+      );
+    }
+    if self.former_members.len() > 0 {
+      ret += "  Former members:\n";
+      for member in &self.former_members {
+        ret += &format!(
+          "   {} (last seen {})\n",
+          member.name,
+          crate::format::timestamp_to_date(member.last_seen)
+        );
+      }
+    }
+    return ret;
+  }
+}
+
+// tags are compared case insensitively, players rarely keep the same capitalization
+pub fn build_roster(
+  tag: &str,
+  names: &std::collections::HashMap<i32, Vec<aliases::NameSeen>>,
+  patterns: &Vec<ClanPattern>,
+) -> ClanRoster {
+  let lowercase_tag = tag.to_lowercase();
+  let has_tag = |name: &str| match extract_clan_tag(name, patterns) {
+    Some(name_tag) => name_tag.to_lowercase() == lowercase_tag,
+    None => false,
+  };
+  let mut members: Vec<RosterMember> = vec![];
+  let mut former_members: Vec<RosterMember> = vec![];
+  for (profile_id, profile_names) in names {
+    let latest = match profile_names
+      .iter()
+      .max_by_key(|name_seen| name_seen.last_seen)
+    {
+      Some(latest) => latest,
+      None => continue,
+    };
+    if has_tag(&latest.name) {
+      members.push(RosterMember {
+        profile_id: *profile_id,
+        name: latest.name.clone(),
+        last_seen: latest.last_seen,
+      });
+    } else if let Some(former) = profile_names
+      .iter()
+      .filter(|name_seen| has_tag(&name_seen.name))
+      .max_by_key(|name_seen| name_seen.last_seen)
+    {
+      former_members.push(RosterMember {
+        profile_id: *profile_id,
+        name: former.name.clone(),
+        last_seen: former.last_seen,
+      });
+    }
+  }
+  members.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+  former_members.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+  ClanRoster {
+    tag: String::from(tag),
+    members,
+    former_members,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::match_filter;
+
+  fn tag(name: &str) -> Option<String> {
+    extract_clan_tag(name, &parse_patterns(DEFAULT_PATTERNS).unwrap())
+  }
+
+  #[test]
+  fn extracts_enclosed_and_separated_tags() {
+    assert_eq!(tag("[KM] Hera"), Some(String::from("KM")));
+    assert_eq!(tag("(KM) Hera"), Some(String::from("KM")));
+    assert_eq!(tag("  <KM>Hera  "), Some(String::from("KM")));
+    assert_eq!(tag("KM|Hera"), Some(String::from("KM")));
+    assert_eq!(tag("Hera"), None);
+  }
+
+  #[test]
+  fn tries_the_patterns_in_order() {
+    // the enclosing pattern comes first, so the separator is part of the tag
+    assert_eq!(tag("[KM|GL] Hera"), Some(String::from("KM|GL")));
+    assert_eq!(tag("KM|[GL] Hera"), Some(String::from("KM")));
+    let separator_only = parse_patterns("|").unwrap();
+    assert_eq!(extract_clan_tag("[KM] Hera", &separator_only), None);
+    assert_eq!(
+      extract_clan_tag("[KM]|Hera", &separator_only),
+      Some(String::from("[KM]"))
+    );
+  }
+
+  #[test]
+  fn rejects_tags_without_a_name_or_too_long() {
+    assert_eq!(tag("[KM]"), None);
+    assert_eq!(tag("KM| "), None);
+    assert_eq!(tag("[] Hera"), None);
+    assert_eq!(tag("[ABCDEFGHI] Hera"), None);
+    assert_eq!(tag("[ABCDEFGH] Hera"), Some(String::from("ABCDEFGH")));
+  }
+
+  #[test]
+  fn handles_multibyte_names() {
+    assert_eq!(tag("[日本] 名前"), Some(String::from("日本")));
+    assert_eq!(tag("ÄÖ|Spieler"), Some(String::from("ÄÖ")));
+    assert_eq!(tag("【KM】 Hera"), None);
+    // the length is counted in characters, not bytes
+    assert_eq!(tag("[ÄÄÄÄÄÄÄÄ] x"), Some(String::from("ÄÄÄÄÄÄÄÄ")));
+    assert_eq!(tag("[ÄÄÄÄÄÄÄÄÄ] x"), None);
+  }
+
+  #[test]
+  fn rejects_invalid_patterns() {
+    assert!(parse_patterns("[]x").is_err());
+    assert!(parse_patterns("").is_err());
+  }
+
+  fn player(
+    profile_id: i32,
+    team: i32,
+    name: &str,
+    won: bool,
+  ) -> fetch::MatchHistoryPlayerResponse {
+    fetch::MatchHistoryPlayerResponse {
+      profile_id: Some(profile_id),
+      team: Some(team),
+      name: Some(String::from(name)),
+      rating: Some(1000),
+      won: Some(won),
+      ..Default::default()
+    }
+  }
+
+  fn game(
+    match_id: &str,
+    started: i64,
+    players: Vec<fetch::MatchHistoryPlayerResponse>,
+  ) -> fetch::MatchHistoryGameResponse {
+    fetch::MatchHistoryGameResponse {
+      match_id: String::from(match_id),
+      num_players: Some(players.len() as i32),
+      started,
+      finished: Some(started as i32 + 1800),
+      players,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn counts_each_clan_once_per_game() {
+    let match_history = vec![
+      game(
+        "2",
+        2000,
+        vec![
+          player(1, 1, "Me", false),
+          player(2, 1, "[KM] Ally", false),
+          player(3, 2, "[GL] First", true),
+          player(5, 2, "Other", true),
+        ],
+      ),
+      game(
+        "1",
+        1000,
+        vec![
+          player(1, 1, "Me", true),
+          player(2, 1, "[KM] Ally", true),
+          player(3, 2, "[GL] First", false),
+          player(4, 2, "[GL] Second", false),
+        ],
+      ),
+    ];
+    let mut tracker = player_tracker::PlayerTracker::new(1);
+    tracker.track_players(
+      &match_history,
+      &vec![],
+      &match_filter::MatchFilter::default(),
+    );
+    let records = analyze(
+      &match_history,
+      &tracker,
+      &parse_patterns(DEFAULT_PATTERNS).unwrap(),
+    );
+    assert_eq!(records.len(), 2);
+    let gl = records.iter().find(|record| record.tag == "GL").unwrap();
+    assert_eq!((gl.wins_against, gl.losses_to), (1, 1));
+    assert_eq!((gl.wins_with, gl.losses_with), (0, 0));
+    assert_eq!(gl.players, vec!["[GL] First", "[GL] Second"]);
+    let km = records.iter().find(|record| record.tag == "KM").unwrap();
+    assert_eq!((km.wins_against, km.losses_to), (0, 0));
+    assert_eq!((km.wins_with, km.losses_with), (1, 1));
+    assert_eq!(km.players, vec!["[KM] Ally"]);
+  }
+}
@@ -0,0 +1,125 @@
+use crate::player_tracker;
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct RecordAgainst {
+  pub games: i32,
+  pub wins_against: i32,
+  pub losses_to: i32,
+  pub win_rate: Option<f64>,
+  // the tracked player's own rating in the games against this opponent
+  pub average_rating: Option<f64>,
+  pub average_opponent_rating: Option<f64>,
+}
+
+impl RecordAgainst {
+  fn from_record(record: &player_tracker::Record) -> RecordAgainst {
+    let results = record.wins_against + record.losses_to;
+    RecordAgainst {
+      games: record.games.len() as i32,
+      wins_against: record.wins_against,
+      losses_to: record.losses_to,
+      win_rate: if results > 0 {
+        Some(record.wins_against as f64 / results as f64)
+      } else {
+        None
+      },
+      average_rating: record.get_average_rating(),
+      average_opponent_rating: record.get_average_opponent_rating(),
+    }
+  }
+  fn format(&self) -> String {
+    let win_rate = match self.win_rate {
+      Some(win_rate) => format!("{:.0}%", win_rate * 100.0),
+      None => String::from("-"),
+    };
+    let ratings = match (self.average_rating, self.average_opponent_rating) {
+      (Some(rating), Some(opponent_rating)) => {
+        format!(", avg elo {:.0} vs {:.0}", rating, opponent_rating)
+      }
+      _ => String::default(),
+    };
+    return format!(
+      "{}-{} ({}){}",
+      self.wins_against, self.losses_to, win_rate, ratings
+    );
+  }
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct CommonOpponent {
+  pub profile_id: i32,
+  pub name: String,
+  pub first: RecordAgainst,
+  pub second: RecordAgainst,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct CommonOpponents {
+  pub first_profile_id: i32,
+  pub second_profile_id: i32,
+  pub opponents: Vec<CommonOpponent>,
+}
+
+impl CommonOpponents {
+  pub fn format(&self, first_name: &str, second_name: &str) -> String {
+    let mut ret = format!(
+      "Common opponents of {} and {}: {}\n",
+      first_name,
+      second_name,
+      self.opponents.len()
+    );
+    for opponent in &self.opponents {
+      ret += &format!(
+        "  {}: {} {}, {} {}\n",
+        opponent.name,
+        first_name,
+        opponent.first.format(),
+        second_name,
+        opponent.second.format()
+      );
+    }
+    return ret;
+  }
+}
+
+// opponents both players have faced, most played first. the two players themselves are left out
+// when they have played each other
+pub fn compare(
+  first: &player_tracker::PlayerTracker,
+  second: &player_tracker::PlayerTracker,
+) -> CommonOpponents {
+  let mut opponents: Vec<CommonOpponent> = vec![];
+  for (profile_id, first_record) in &first.records {
+    if *profile_id == first.profile_id || *profile_id == second.profile_id {
+      continue;
+    }
+    let second_record = match second.records.get(profile_id) {
+      Some(record) => record,
+      None => continue,
+    };
+    let name = match first
+      .players
+      .get(profile_id)
+      .or(second.players.get(profile_id))
+    {
+      Some(player) => player.get_name(),
+      None => profile_id.to_string(),
+    };
+    opponents.push(CommonOpponent {
+      profile_id: *profile_id,
+      name,
+      first: RecordAgainst::from_record(first_record),
+      second: RecordAgainst::from_record(second_record),
+    });
+  }
+  opponents.sort_by(|a, b| {
+    (b.first.games + b.second.games)
+      .cmp(&(a.first.games + a.second.games))
+      .then(a.name.cmp(&b.name))
+  });
+  CommonOpponents {
+    first_profile_id: first.profile_id,
+    second_profile_id: second.profile_id,
+    opponents,
+  }
+}
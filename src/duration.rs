@@ -0,0 +1,109 @@
+use crate::fetch;
+use serde::ser::{Serialize, Serializer, SerializeStruct};
+
+// real time, not in game time: games under 20 minutes are rushes, over 40 minutes are long imperial games
+const EARLY_GAME_SECS: i64 = 20 * 60;
+const LONG_GAME_SECS: i64 = 40 * 60;
+
+pub fn format_duration(secs: i64) -> String {
+  return format!("{}m {:02}s", secs / 60, secs % 60);
+}
+
+#[derive(serde::Serialize, Debug, Clone, Default)]
+pub struct BucketRecord {
+  pub games: i32,
+  pub wins: i32,
+  pub losses: i32,
+}
+
+impl BucketRecord {
+  fn add(&mut self, is_win: Option<bool>) {
+    self.games += 1;
+    match is_win {
+      Some(true) => self.wins += 1,
+      Some(false) => self.losses += 1,
+      None => (),
+    }
+  }
+  pub fn win_rate(&self) -> Option<f64> {
+    if self.wins + self.losses == 0 {
+      return None;
+    }
+    Some(self.wins as f64 / (self.wins + self.losses) as f64)
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DurationStats {
+  pub games: i32,
+  pub total_secs: i64,
+  pub early: BucketRecord,
+  pub mid: BucketRecord,
+  pub long: BucketRecord,
+}
+
+impl Serialize for DurationStats {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    let mut state = serializer.serialize_struct("DurationStats", 5)?;
+    state.serialize_field("games", &self.games)?;
+    state.serialize_field("average_secs", &self.average_secs())?;
+    state.serialize_field("early", &self.early)?;
+    state.serialize_field("mid", &self.mid)?;
+    state.serialize_field("long", &self.long)?;
+    state.end()
+  }
+}
+
+impl DurationStats {
+  // is_win is None for games that count toward the length but not a result
+  pub fn add(&mut self, game: &fetch::MatchHistoryGameResponse, is_win: Option<bool>) {
+    let secs = match game.get_duration_secs() {
+      Some(secs) => secs,
+      None => return,
+    };
+    self.games += 1;
+    self.total_secs += secs;
+    if secs < EARLY_GAME_SECS {
+      self.early.add(is_win);
+    } else if secs > LONG_GAME_SECS {
+      self.long.add(is_win);
+    } else {
+      self.mid.add(is_win);
+    }
+  }
+  pub fn average_secs(&self) -> Option<i64> {
+    if self.games == 0 {
+      return None;
+    }
+    Some(self.total_secs / self.games as i64)
+  }
+  pub fn format(&self) -> String {
+    let average = match self.average_secs() {
+      Some(secs) => format_duration(secs),
+      None => return String::from("no finished games"),
+    };
+    let bucket_to_string = |bucket: &BucketRecord| -> String {
+      match bucket.win_rate() {
+        Some(win_rate) => format!(
+          "{}-{} ({:.0}%)",
+          bucket.wins,
+          bucket.losses,
+          win_rate * 100.0
+        ),
+        None => String::from("no games"),
+      }
+    };
+    return format!(
+      "average {}, early (<{}m) {}, mid {}, long (>{}m) {}",
+      average,
+      EARLY_GAME_SECS / 60,
+      bucket_to_string(&self.early),
+      bucket_to_string(&self.mid),
+      LONG_GAME_SECS / 60,
+      bucket_to_string(&self.long)
+    );
+  }
+}
@@ -7,7 +7,7 @@ use crate::error::RuntimeError;
 
 const AOE2NET_API_BASE_URL: &'static str = "https://aoe2.net/api";
 
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone)]
 pub enum LeaderboardId {
   Unranked = 2,
   RankedSolo = 3,
@@ -79,6 +79,8 @@ pub struct MatchHistoryPlayerResponse {
   pub name: Option<String>,
   pub rating: Option<i32>,
   pub won: Option<bool>,
+  pub civ: Option<i32>,
+  pub color: Option<i32>,
 }
 
 impl MatchHistoryPlayerResponse {
@@ -106,6 +108,12 @@ impl MatchHistoryPlayerResponse {
       Some(r) => r,
     }
   }
+  pub fn get_team_id(&self) -> Option<i32> {
+    match self.team {
+      Some(team) if team > 0 => Some(team),
+      _ => None,
+    }
+  }
   pub fn is_win(&self) -> bool {
     match self.won {
       Some(value) => value,
@@ -114,6 +122,44 @@ impl MatchHistoryPlayerResponse {
   }
 }
 
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+pub struct RatingHistoryResponse {
+  pub rating: i32,
+  pub num_wins: i32,
+  pub num_losses: i32,
+  pub streak: Option<i32>,
+  pub drops: Option<i32>,
+  pub timestamp: i64,
+}
+
+// the names aoe2.net gives the ids of civs and map types
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+pub struct StringResponse {
+  pub id: i32,
+  pub string: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+pub struct StringsResponse {
+  pub civ: Vec<StringResponse>,
+  pub map_type: Vec<StringResponse>,
+}
+
+impl StringsResponse {
+  fn get_string(strings: &Vec<StringResponse>, id: i32) -> Option<String> {
+    strings
+      .iter()
+      .find(|string| string.id == id)
+      .map(|string| string.string.clone())
+  }
+  pub fn get_civ_name(&self, civ: i32) -> Option<String> {
+    StringsResponse::get_string(&self.civ, civ)
+  }
+  pub fn get_map_name(&self, map_type: i32) -> Option<String> {
+    StringsResponse::get_string(&self.map_type, map_type)
+  }
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
 pub struct LastMatchResponse {
   pub profile_id: i32,
@@ -130,6 +176,7 @@ pub struct MatchHistoryGameResponse {
   pub lobby_id: Option<String>,
   pub match_uuid: Option<String>,
   pub version: Option<String>,
+  pub map_type: Option<i32>,
   pub finished: Option<i32>,
   pub players: Vec<MatchHistoryPlayerResponse>,
 }
@@ -169,54 +216,334 @@ impl MatchHistoryGameResponse {
       None => LeaderboardId::Unranked,
     }
   }
-  pub fn get_team_id_by_profile_id(&self, profile_id: i32) -> i32 {
-    for player in &self.players {
-      if player.get_profile_id() == profile_id {
-        return player.get_team();
-      }
+  pub fn get_team_id_by_profile_id(&self, profile_id: i32) -> Option<i32> {
+    match self.get_player_by_profile_id(profile_id) {
+      Some(player) => player.get_team_id(),
+      None => None,
     }
-    1
   }
-  pub fn get_profile_ids_on_team(&self, team: i32) -> Vec<i32> {
-    let mut ret: Vec<i32> = vec![];
+  // players without a team are each placed on a team of their own
+  pub fn get_teams(&self) -> Vec<Vec<&MatchHistoryPlayerResponse>> {
+    let mut teams: Vec<(Option<i32>, Vec<&MatchHistoryPlayerResponse>)> = vec![];
     for player in &self.players {
-      if player.get_team() == team {
-        ret.push(player.get_profile_id());
+      let team_id = player.get_team_id();
+      if team_id.is_some() {
+        if let Some((_, team)) = teams.iter_mut().find(|(id, _)| *id == team_id) {
+          team.push(player);
+          continue;
+        }
       }
+      teams.push((team_id, vec![player]));
     }
-    return ret;
+    teams.sort_by_key(|(id, _)| id.unwrap_or(std::i32::MAX));
+    return teams.into_iter().map(|(_, team)| team).collect();
+  }
+  pub fn get_team_count(&self) -> usize {
+    return self.get_teams().len();
+  }
+  pub fn is_free_for_all(&self) -> bool {
+    return self.players.len() > 2 && self.get_team_count() == self.players.len();
   }
-  pub fn get_opposing_team_profile_ids(&self, my_profile_id: i32) -> Vec<i32> {
-    let my_team_id = self.get_team_id_by_profile_id(my_profile_id);
-    let mut other_team_id = 1;
-    if my_team_id == 1 {
-      other_team_id = 2;
+  // e.g. "2v2", or "ffa" for free for all games
+  pub fn get_format(&self) -> String {
+    if self.is_free_for_all() {
+      return String::from("ffa");
     }
-    return self.get_profile_ids_on_team(other_team_id);
+    let team_sizes: Vec<String> = self
+      .get_teams()
+      .iter()
+      .map(|team| team.len().to_string())
+      .collect();
+    return team_sizes.join("v");
   }
-  pub fn get_opposing_team(&self, my_profile_id: i32) -> Vec<&MatchHistoryPlayerResponse> {
-    let my_team_id = self.get_team_id_by_profile_id(my_profile_id);
-    let mut other_team_id = 1;
-    if my_team_id == 1 {
-      other_team_id = 2;
+  // the number of players on each side, for games between two teams of the same size
+  pub fn get_team_size(&self) -> Option<i32> {
+    let teams = self.get_teams();
+    let team_size = teams.get(0)?.len();
+    if teams.len() == 2 && teams.iter().all(|team| team.len() == team_size) {
+      Some(team_size as i32)
+    } else {
+      None
     }
-    return self.get_team(other_team_id);
+  }
+  pub fn get_opposing_teams(&self, my_profile_id: i32) -> Vec<Vec<&MatchHistoryPlayerResponse>> {
+    let mut teams = self.get_teams();
+    teams.retain(|team| {
+      !team
+        .iter()
+        .any(|player| player.get_profile_id() == my_profile_id)
+    });
+    return teams;
+  }
+  pub fn get_opposing_team(&self, my_profile_id: i32) -> Vec<&MatchHistoryPlayerResponse> {
+    return self
+      .get_opposing_teams(my_profile_id)
+      .into_iter()
+      .flatten()
+      .collect();
   }
   pub fn get_my_team(&self, my_profile_id: i32) -> Vec<&MatchHistoryPlayerResponse> {
-    let my_team_id = self.get_team_id_by_profile_id(my_profile_id);
-    let mut my_team = self.get_team(my_team_id);
+    let mut my_team = match self.get_team_id_by_profile_id(my_profile_id) {
+      Some(my_team_id) => self.get_team(my_team_id),
+      None => vec![],
+    };
     my_team.retain(|player| player.get_profile_id() != my_profile_id);
     return my_team;
   }
+  // with more than two teams, two players who both lost did not beat each other
+  pub fn get_result_against(
+    &self,
+    is_win: bool,
+    other_player: &MatchHistoryPlayerResponse,
+  ) -> Option<bool> {
+    if is_win {
+      Some(true)
+    } else if other_player.is_win() || self.get_team_count() <= 2 {
+      Some(false)
+    } else {
+      None
+    }
+  }
+  pub fn get_duration_secs(&self) -> Option<i64> {
+    match self.finished {
+      Some(finished) if finished as i64 > self.started => Some(finished as i64 - self.started),
+      _ => None,
+    }
+  }
   pub fn get_match_id(&self) -> &str {
     return &self.match_id;
   }
 }
 
+// every request to aoe2.net goes through here. identical requests that are in flight at the same
+// time share one response, all requests together stay within a requests per second budget, and
+// requests that fail in a way that might not happen again are retried with a growing delay
+
+// can be set with the AOE2NET_REQUESTS_PER_SEC environment variable
+const DEFAULT_REQUESTS_PER_SEC: f64 = 4.0;
+const MAX_ATTEMPTS: u32 = 4;
+const BASE_RETRY_DELAY_MS: u64 = 500;
+// requests that would have to wait longer than this for a slot fail instead of queueing
+const MAX_QUEUE_WAIT_SECS: u64 = 10;
+
+#[derive(Debug, Clone)]
+struct UpstreamResponse {
+  url: String,
+  status: u16,
+  body: String,
+  // only the number of seconds form of the header is read
+  retry_after: Option<std::time::Duration>,
+}
+
+impl UpstreamResponse {
+  fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, RuntimeError> {
+    if self.status < 200 || self.status >= 300 {
+      return Err(RuntimeError::new(&format!(
+        "aoe2.net returned {} for {}",
+        self.status, self.url
+      )));
+    }
+    match serde_json::from_str(&self.body) {
+      Ok(value) => Ok(value),
+      Err(e) => Err(RuntimeError::new(&format!(
+        "Could not parse the response for {}: {}",
+        self.url, e
+      ))),
+    }
+  }
+}
+
+type SharedRequest = futures::future::Shared<
+  std::pin::Pin<Box<dyn std::future::Future<Output = Result<UpstreamResponse, String>> + Send>>,
+>;
+
+lazy_static::lazy_static! {
+  static ref REQUEST_INTERVAL: std::time::Duration = {
+    let requests_per_sec = std::env::var("AOE2NET_REQUESTS_PER_SEC")
+      .ok()
+      .and_then(|value| value.parse::<f64>().ok())
+      .filter(|value| *value > 0.0)
+      .unwrap_or(DEFAULT_REQUESTS_PER_SEC);
+    std::time::Duration::from_secs_f64(1.0 / requests_per_sec)
+  };
+  static ref NEXT_SLOT: std::sync::Mutex<std::time::Instant> =
+    std::sync::Mutex::new(std::time::Instant::now());
+  static ref IN_FLIGHT: std::sync::Mutex<std::collections::HashMap<String, SharedRequest>> =
+    std::sync::Mutex::new(std::collections::HashMap::new());
+}
+
+// takes the next free slot in the budget and returns how long to wait for it, or an error if the
+// queue is too long to wait for
+fn reserve_slot() -> Result<std::time::Duration, String> {
+  let mut next_slot = NEXT_SLOT.lock().unwrap();
+  let now = std::time::Instant::now();
+  let slot = if *next_slot > now { *next_slot } else { now };
+  if slot - now > std::time::Duration::from_secs(MAX_QUEUE_WAIT_SECS) {
+    return Err(format!(
+      "Too many requests to aoe2.net are queued, waiting would take {}s",
+      (slot - now).as_secs()
+    ));
+  }
+  *next_slot = slot + *REQUEST_INTERVAL;
+  Ok(slot - now)
+}
+
+// no request is sent before the given time, which is how a 429 from aoe2.net slows down every
+// request and not just the one that got it
+fn pause_until(until: std::time::Instant) {
+  let mut next_slot = NEXT_SLOT.lock().unwrap();
+  if until > *next_slot {
+    *next_slot = until;
+  }
+}
+
+fn get_retry_after(headers: &reqwest::header::HeaderMap) -> Option<std::time::Duration> {
+  headers
+    .get(reqwest::header::RETRY_AFTER)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.trim().parse::<u64>().ok())
+    .map(std::time::Duration::from_secs)
+}
+
+fn is_transient_status(status: u16) -> bool {
+  status == 429 || status >= 500
+}
+
+// reqwest 0.10.6 can only tell timeouts apart from other errors
+fn is_transient_error(error: &reqwest::Error) -> bool {
+  error.is_timeout()
+}
+
+// the delay doubles with every attempt, plus a random part of the same size so that clients that
+// failed together do not all retry at the same time
+fn get_retry_delay(attempt: u32) -> std::time::Duration {
+  use rand::Rng;
+  let delay_ms = BASE_RETRY_DELAY_MS * 2u64.pow(attempt - 1);
+  let jitter_ms = rand::thread_rng().gen_range(0, delay_ms);
+  std::time::Duration::from_millis(delay_ms + jitter_ms)
+}
+
+// none if the result should be returned as it is
+fn should_retry(
+  result: &Result<UpstreamResponse, reqwest::Error>,
+  attempt: u32,
+) -> Option<std::time::Duration> {
+  let is_transient = match result {
+    Ok(response) => is_transient_status(response.status),
+    Err(error) => is_transient_error(error),
+  };
+  if is_transient && attempt < MAX_ATTEMPTS {
+    let delay = match result {
+      Ok(UpstreamResponse {
+        status: 429,
+        retry_after: Some(retry_after),
+        ..
+      }) => {
+        pause_until(std::time::Instant::now() + *retry_after);
+        // waiting longer than a queued request would is not worth it
+        if *retry_after > std::time::Duration::from_secs(MAX_QUEUE_WAIT_SECS) {
+          println!(
+            "[fetch] aoe2.net asked to wait {}s, giving up",
+            retry_after.as_secs()
+          );
+          return None;
+        }
+        *retry_after
+      }
+      _ => get_retry_delay(attempt),
+    };
+    println!(
+      "[fetch] Attempt {} failed, retrying in {}ms",
+      attempt,
+      delay.as_millis()
+    );
+    Some(delay)
+  } else {
+    None
+  }
+}
+
+async fn send_async(url: String) -> Result<UpstreamResponse, String> {
+  let mut attempt = 1;
+  loop {
+    actix_rt::time::delay_for(reserve_slot()?).await;
+    let result = match reqwest::get(&url).await {
+      Ok(res) => {
+        let status = res.status().as_u16();
+        let retry_after = get_retry_after(res.headers());
+        res.text().await.map(|body| UpstreamResponse {
+          url: url.clone(),
+          status,
+          body,
+          retry_after,
+        })
+      }
+      Err(e) => Err(e),
+    };
+    match should_retry(&result, attempt) {
+      Some(delay) => actix_rt::time::delay_for(delay).await,
+      None => return result.map_err(|e| e.to_string()),
+    }
+    attempt += 1;
+  }
+}
+
+async fn get_async(url: &str) -> Result<UpstreamResponse, RuntimeError> {
+  use futures::FutureExt;
+  let request = {
+    let mut in_flight = IN_FLIGHT.lock().unwrap();
+    match in_flight.get(url) {
+      Some(request) => {
+        println!("[fetch] Joining the request in flight for {}", url);
+        request.clone()
+      }
+      None => {
+        let owned_url = String::from(url);
+        let request: SharedRequest = async move {
+          let result = send_async(owned_url.clone()).await;
+          IN_FLIGHT.lock().unwrap().remove(&owned_url);
+          result
+        }
+        .boxed()
+        .shared();
+        in_flight.insert(String::from(url), request.clone());
+        request
+      }
+    }
+  };
+  request.await.map_err(|e| RuntimeError::new(&e))
+}
+
+// the command line makes one request at a time, so there is nothing to coalesce
+fn get(url: &str) -> Result<UpstreamResponse, RuntimeError> {
+  let mut attempt = 1;
+  loop {
+    std::thread::sleep(reserve_slot().map_err(|e| RuntimeError::new(&e))?);
+    let result = match reqwest::blocking::get(url) {
+      Ok(res) => {
+        let status = res.status().as_u16();
+        let retry_after = get_retry_after(res.headers());
+        res.text().map(|body| UpstreamResponse {
+          url: String::from(url),
+          status,
+          body,
+          retry_after,
+        })
+      }
+      Err(e) => Err(e),
+    };
+    match should_retry(&result, attempt) {
+      Some(delay) => std::thread::sleep(delay),
+      None => return Ok(result?),
+    }
+    attempt += 1;
+  }
+}
+
 pub fn fetch_player(
   name: &str,
   leaderboard_id: LeaderboardId,
-) -> Result<Option<PlayerResponse>, reqwest::Error> {
+) -> Result<Option<PlayerResponse>, RuntimeError> {
   let url = format!(
     "{}/leaderboard?start=1&leaderboard_id={leaderboard_id}&search={name}",
     AOE2NET_API_BASE_URL,
@@ -224,8 +551,7 @@ pub fn fetch_player(
     name = name
   );
   println!("[fetch] {}", url);
-  let res = reqwest::blocking::get(&url)?;
-  let players: LookupPlayerResponse = res.json()?;
+  let players: LookupPlayerResponse = get(&url)?.json()?;
   if players.count == 0 {
     return Ok(None);
   }
@@ -235,7 +561,7 @@ pub fn fetch_player(
 pub async fn fetch_player_async(
   name: &str,
   leaderboard_id: LeaderboardId,
-) -> Result<Option<PlayerResponse>, reqwest::Error> {
+) -> Result<Option<PlayerResponse>, RuntimeError> {
   let url = format!(
     "{}/leaderboard?start=1&leaderboard_id={leaderboard_id}&search={name}",
     AOE2NET_API_BASE_URL,
@@ -243,7 +569,7 @@ pub async fn fetch_player_async(
     name = name
   );
   println!("[fetch] {}", url);
-  let players: LookupPlayerResponse = reqwest::get(&url).await?.json().await?;
+  let players: LookupPlayerResponse = get_async(&url).await?.json()?;
   if players.count == 0 {
     return Ok(None);
   }
@@ -267,9 +593,9 @@ pub fn fetch_rating(name: &str, leaderboard_id: LeaderboardId) -> i32 {
   };
 }
 
-pub fn fetch_match_history(
+pub async fn fetch_match_history_async(
   profile_id: i32,
-) -> Result<Option<Vec<MatchHistoryGameResponse>>, reqwest::Error> {
+) -> Result<Option<Vec<MatchHistoryGameResponse>>, RuntimeError> {
   println!("Get match history for id: '{}'", profile_id);
   let url = format!(
     "{}/player/matches?game=aoe2de&start=0&count=9999&profile_id={profile_id}",
@@ -277,28 +603,29 @@ pub fn fetch_match_history(
     profile_id = profile_id,
   );
   println!("[fetch] {}", url);
-  let res = reqwest::blocking::get(&url)?;
-  let match_history: Vec<MatchHistoryGameResponse> = res.json()?;
+  let match_history: Vec<MatchHistoryGameResponse> = get_async(&url).await?.json()?;
   Ok(Some(match_history))
 }
 
-pub async fn fetch_match_history_async(
+pub async fn fetch_rating_history_async(
   profile_id: i32,
-) -> Result<Option<Vec<MatchHistoryGameResponse>>, reqwest::Error> {
-  println!("Get match history for id: '{}'", profile_id);
+  leaderboard_id: LeaderboardId,
+) -> Result<Option<Vec<RatingHistoryResponse>>, RuntimeError> {
+  println!("Get rating history for id: '{}'", profile_id);
   let url = format!(
-    "{}/player/matches?game=aoe2de&start=0&count=9999&profile_id={profile_id}",
+    "{}/player/ratinghistory?game=aoe2de&leaderboard_id={leaderboard_id}&count=9999&profile_id={profile_id}",
     AOE2NET_API_BASE_URL,
+    leaderboard_id = leaderboard_id,
     profile_id = profile_id,
   );
   println!("[fetch] {}", url);
-  let match_history: Vec<MatchHistoryGameResponse> = reqwest::get(&url).await?.json().await?;
-  Ok(Some(match_history))
+  let rating_history: Vec<RatingHistoryResponse> = get_async(&url).await?.json()?;
+  Ok(Some(rating_history))
 }
 
 pub async fn fetch_latest_match_async(
   profile_id: i32,
-) -> Result<Option<LastMatchResponse>, reqwest::Error> {
+) -> Result<Option<LastMatchResponse>, RuntimeError> {
   println!("Get lst match id: '{}'", profile_id);
   let url = format!(
     "{}/player/matches?game=aoe2de&start=0&count=1&profile_id={profile_id}",
@@ -306,9 +633,33 @@ pub async fn fetch_latest_match_async(
     profile_id = profile_id,
   );
   println!("[fetch] {}", url);
-  let match_history: Vec<MatchHistoryGameResponse> = reqwest::get(&url).await?.json().await?;
+  let match_history: Vec<MatchHistoryGameResponse> = get_async(&url).await?.json()?;
   Ok(Some(LastMatchResponse {
     profile_id,
-    last_match: Some(match_history[0].clone()),
+    last_match: match_history.get(0).cloned(),
   }))
 }
+
+pub async fn fetch_match_async(
+  match_id: &str,
+) -> Result<Option<MatchHistoryGameResponse>, RuntimeError> {
+  let url = format!(
+    "{}/match?game=aoe2de&match_id={match_id}",
+    AOE2NET_API_BASE_URL,
+    match_id = match_id,
+  );
+  println!("[fetch] {}", url);
+  let res = get_async(&url).await?;
+  if res.status == 404 {
+    return Ok(None);
+  }
+  let game: MatchHistoryGameResponse = res.json()?;
+  Ok(Some(game))
+}
+
+pub async fn fetch_strings_async() -> Result<Option<StringsResponse>, RuntimeError> {
+  let url = format!("{}/strings?game=aoe2de&language=en", AOE2NET_API_BASE_URL);
+  println!("[fetch] {}", url);
+  let strings: StringsResponse = get_async(&url).await?.json()?;
+  Ok(Some(strings))
+}
@@ -1,14 +1,36 @@
+mod aliases;
+mod args;
+mod balance;
+mod batch;
+mod clan;
+mod common_opponents;
+mod duration;
 mod error;
 mod fetch;
 mod format;
+mod match_detail;
+mod match_filter;
+mod outcome;
 mod player_tracker;
+mod prediction;
+mod premade;
+mod rating;
+mod record_page;
+mod rivals;
 mod server;
+mod session;
+mod time_of_day;
+mod tracking;
+mod watch;
 
 use error::RuntimeError;
 use std::env;
 use std::fs::File;
 use std::io::Write;
 
+// options that are on without a value
+const FLAGS: [&str; 4] = ["ranked", "watch", "batch", "balance"];
+
 fn write_output_csv(
   player_name: String,
   records: &std::collections::HashMap<i32, player_tracker::Record>,
@@ -19,7 +41,7 @@ fn write_output_csv(
   let mut output = File::create(filename)?;
   write!(
     output,
-    "profile_id,player_name,num_games,wins_against,losses_to,elo,date_last_played\n"
+    "profile_id,player_name,num_games,wins_against,losses_to,elo,date_last_played,expected_wins,wins_above_expected,avg_elo,min_elo,max_elo,avg_elo_difference\n"
   )?;
 
   for (enemy_profile_id, record) in records.iter() {
@@ -36,7 +58,55 @@ fn write_output_csv(
   Ok(())
 }
 
-fn run(player_name_arg: &str, leaderboard_name_arg: &str) -> Result<(), RuntimeError> {
+// tracks the history on the command line, where there is no async runtime around
+fn track_history(
+  profile_id: i32,
+  leaderboard_id: fetch::LeaderboardId,
+  filter: &match_filter::MatchFilter,
+) -> Result<tracking::TrackedHistory, RuntimeError> {
+  let filter = filter.clone();
+  actix_rt::System::new("track").block_on(async move {
+    tracking::track_history(
+      profile_id,
+      leaderboard_id,
+      &filter,
+      tracking::fetch_match_history,
+      tracking::fetch_rating_history,
+    )
+    .await
+  })
+}
+
+// builds a tracker for another player, from the same leaderboard and filters
+fn track_profile(
+  profile_id: i32,
+  leaderboard_id: fetch::LeaderboardId,
+  filter: &match_filter::MatchFilter,
+) -> Result<(String, player_tracker::PlayerTracker), RuntimeError> {
+  let tracked = track_history(profile_id, leaderboard_id, filter)?;
+  let player_name = match tracked.match_history[0].get_player_by_profile_id(profile_id) {
+    Some(player) => player.get_name(),
+    None => return Err(RuntimeError::new("Player has not played any games.")),
+  };
+  Ok((player_name, tracked.tracker))
+}
+
+fn run(
+  player_name_arg: &str,
+  leaderboard_name_arg: &str,
+  args: &args::Args,
+) -> Result<(), RuntimeError> {
+  let filter = match_filter::MatchFilter::from_args(args)?;
+  let timezone = match args.get("timezone").or(args.get("utc-offset")) {
+    Some(timezone) => time_of_day::parse_timezone(timezone)?,
+    None => time_of_day::Timezone::utc(),
+  };
+  let session_gap_secs = match args.get_i32("session-gap")? {
+    Some(minutes) => minutes as i64 * 60,
+    None => session::DEFAULT_SESSION_GAP_SECS,
+  };
+  let clan_patterns =
+    clan::parse_patterns(args.get("clan-patterns").unwrap_or(clan::DEFAULT_PATTERNS))?;
   let leaderboard_id = format::get_leaderboard_id_from_name(&leaderboard_name_arg);
   let leaderboard_id_alt = format::get_opposite_leaderboard_id(leaderboard_id);
   let leaderboard_name = format::leaderboard_id_to_name(leaderboard_id);
@@ -55,24 +125,20 @@ fn run(player_name_arg: &str, leaderboard_name_arg: &str) -> Result<(), RuntimeE
   };
 
   let profile_id = player_resp.profile_id;
-  let mut pt = player_tracker::PlayerTracker::new(profile_id);
-
-  let mut match_history = match fetch::fetch_match_history(profile_id)? {
-    Some(m) => m,
-    None => return Err(RuntimeError::new("Could not get match history.")),
-  };
 
-  println!("Retaining match history: {} games", match_history.len());
-
-  match_history.retain(|mh| mh.get_leaderboard_id() == leaderboard_id);
-
-  if match_history.len() == 0 {
-    return Err(RuntimeError::new("Player has not played any team games."));
+  if let Err(e) = server::cache::init_cache() {
+    println!("Could not open the cache database: {}", e);
   }
-
-  println!("Processing match history: {} games", match_history.len());
-
-  pt.track_players(&match_history);
+  let tracking::TrackedHistory {
+    match_history,
+    tracker: pt,
+    includes_newest_game,
+  } = track_history(profile_id, leaderboard_id, &filter)?;
+  let current_rating = if includes_newest_game {
+    player_resp.rating
+  } else {
+    None
+  };
 
   let most_recent_game = &match_history[0];
   println!("");
@@ -82,38 +148,67 @@ fn run(player_name_arg: &str, leaderboard_name_arg: &str) -> Result<(), RuntimeE
   );
   println!("");
 
-  let my_player_in_game = match most_recent_game.get_player_by_profile_id(profile_id) {
-    Some(p) => p,
-    None => panic!("Player who was looked up not found in game somehow."),
-  };
+  if most_recent_game
+    .get_player_by_profile_id(profile_id)
+    .is_none()
+  {
+    panic!("Player who was looked up not found in game somehow.");
+  }
 
-  let other_team = most_recent_game.get_opposing_team(profile_id);
+  let other_teams = most_recent_game.get_opposing_teams(profile_id);
   let mut other_team_names: String = String::default();
   let mut other_team_records: String = String::default();
-  for &enemy_player in &other_team {
-    let alt_elo = fetch::fetch_rating(&enemy_player.get_name(), leaderboard_id_alt);
-    other_team_names += &format!("{}", enemy_player.get_name());
-    let (wins, losses) = pt.get_win_loss_record(enemy_player.get_profile_id());
-    other_team_records += &format!(
-      "  {} {}: wins against {}, losses to {}\n",
-      enemy_player.get_name(),
-      format::ratings_to_string(
-        &leaderboard_name,
-        enemy_player.get_rating(),
-        &leaderboard_name_alt,
-        alt_elo
-      ),
-      wins,
-      losses
-    );
+  for (i, other_team) in other_teams.iter().enumerate() {
+    if other_teams.len() > 1 && other_team.len() > 1 {
+      other_team_records += &format!(" Opposing team {}\n", i + 1);
+    }
+    for &enemy_player in other_team {
+      let alt_elo = fetch::fetch_rating(&enemy_player.get_name(), leaderboard_id_alt);
+      other_team_names += &format!("{}", enemy_player.get_name());
+      let (wins, losses) = pt.get_win_loss_record(enemy_player.get_profile_id());
+      other_team_records += &format!(
+        "  {}{} {}: wins against {}, losses to {}\n",
+        enemy_player.get_name(),
+        pt.format_aliases(enemy_player.get_profile_id()),
+        format::ratings_to_string(
+          &leaderboard_name,
+          enemy_player.get_rating(),
+          &leaderboard_name_alt,
+          alt_elo
+        ),
+        wins,
+        losses
+      );
+      if let Some(record) = pt.records.get(&enemy_player.get_profile_id()) {
+        if record.formats.len() > 1 {
+          other_team_records += &format!(
+            "    by format: {}\n",
+            player_tracker::format_breakdown(&record.formats)
+          );
+        }
+        if let Some(average_secs) = record.durations.average_secs() {
+          other_team_records += &format!(
+            "    average game length: {}\n",
+            duration::format_duration(average_secs)
+          );
+        }
+        if record.ratings.len() > 1 {
+          other_team_records += &format!("    elo in past games: {}\n", record.format_ratings());
+        }
+        if record.performance.games > 0 {
+          other_team_records += &format!("    vs expectation: {}\n", record.performance.format());
+        }
+      }
+    }
   }
   let my_team = most_recent_game.get_my_team(profile_id);
   let mut my_team_names = String::from("\n");
   for &ally_player in &my_team {
     let alt_elo = fetch::fetch_rating(&ally_player.get_name(), leaderboard_id_alt);
     my_team_names += &format!(
-      "  {}:({} elo in game={}, current {} elo={})\n",
+      "  {}{}:({} elo in game={}, current {} elo={})\n",
       ally_player.get_name(),
+      pt.format_aliases(ally_player.get_profile_id()),
       leaderboard_name,
       ally_player.get_rating(),
       leaderboard_name_alt,
@@ -137,13 +232,46 @@ fn run(player_name_arg: &str, leaderboard_name_arg: &str) -> Result<(), RuntimeE
   if my_team_names.len() > 1 {
     println!(" Teammates: {}", my_team_names);
   }
+  println!(" Vs expectation: {}", pt.performance.format());
+  println!(
+    " By format: {}",
+    player_tracker::format_breakdown(&pt.formats)
+  );
+  println!(" Game length: {}", pt.durations.format());
+  let inferred_outcomes = pt.get_inferred_outcomes();
+  if inferred_outcomes.len() > 0 {
+    println!(
+      " Results inferred for {} games without a reported result:",
+      inferred_outcomes.len()
+    );
+    for outcome in inferred_outcomes {
+      println!(
+        "  {} {}: {}",
+        format::timestamp_to_date(outcome.started),
+        outcome.match_id,
+        outcome.describe()
+      );
+    }
+  }
+  if pt.ffa_wins + pt.ffa_losses > 0 {
+    println!(
+      " Free for all: {} wins, {} losses",
+      pt.ffa_wins, pt.ffa_losses
+    );
+  }
   println!("---");
 
   let is_game_in_progress = match most_recent_game.finished {
     Some(_) => {
+      let outcome = pt.get_outcome(most_recent_game.get_match_id());
+      let victory = match outcome.and_then(|outcome| outcome.is_win) {
+        Some(is_win) => is_win.to_string(),
+        None => String::from("unknown"),
+      };
       println!(
-        "Most recent game completed. Victory? {}.",
-        my_player_in_game.is_win()
+        "Most recent game completed. Victory? {} ({}).",
+        victory,
+        outcome.map_or(String::from("unknown"), |outcome| outcome.describe())
       );
       false
     }
@@ -165,12 +293,98 @@ fn run(player_name_arg: &str, leaderboard_name_arg: &str) -> Result<(), RuntimeE
   );
   println!("{}", other_team_records);
 
+  for opposing_premade in
+    premade::detect_opposing_premades(most_recent_game, &match_history, profile_id)
+  {
+    println!(
+      "Opposing team looks {}: {} (up to {} games together)",
+      if opposing_premade.queue_type == premade::QueueType::Premade {
+        "premade"
+      } else {
+        "partly premade"
+      },
+      opposing_premade.names.join(", "),
+      opposing_premade.games_together
+    );
+  }
+
+  if is_game_in_progress {
+    let prediction = prediction::predict_game(most_recent_game, &pt);
+    println!("{}", prediction.explain());
+  }
+
+  let time_of_day_stats = time_of_day::TimeOfDayStats::from_outcomes(&pt.outcomes, &timezone);
+  println!("{}", time_of_day_stats.format());
+
+  let sessions =
+    session::detect_sessions(&match_history, &pt, session_gap_secs, current_rating);
+  if let Some(last_session) = sessions.get(0) {
+    println!("Last session: {}", last_session.format());
+  }
+
+  let premade_stats = premade::analyze(&match_history, &pt, &sessions);
+  println!("Team games by queue: {}", premade_stats.format());
+
+  let rival_options = rivals::RivalOptions {
+    min_games: args
+      .get_i32("rivals-min-games")?
+      .unwrap_or(rivals::DEFAULT_MIN_GAMES),
+    count: args
+      .get_i32("rivals-count")?
+      .map(|count| count.max(0) as usize)
+      .unwrap_or(rivals::DEFAULT_COUNT),
+  };
+  println!("{}", rivals::rank_rivals(&pt, &rival_options).format());
+
+  let clan_records = clan::analyze(&match_history, &pt, &clan_patterns);
+  if clan_records.len() > 0 {
+    println!("Clans:");
+    for clan_record in &clan_records {
+      println!("  {}", clan_record.format());
+    }
+    println!("");
+  }
+  if let Some(other_profile_id) = args.get_i32("compare")? {
+    let (other_name, other_pt) = track_profile(other_profile_id, leaderboard_id, &filter)?;
+    println!(
+      "{}",
+      common_opponents::compare(&pt, &other_pt).format(&player_resp.name, &other_name)
+    );
+  }
+  if let Some(tag) = args.get("clan-roster") {
+    match server::cache::select_all_player_names() {
+      Ok(names) => println!(
+        "{}",
+        clan::build_roster(tag, &names, &clan_patterns).format()
+      ),
+      Err(e) => println!("Could not read player names: {}", e),
+    }
+  }
+
   write_output_csv(
     format!("{}_{}", player_resp.name, leaderboard_name),
     &pt.records,
     &pt.players,
   )?;
 
+  if args.get_bool("watch")? {
+    let options = watch::WatchOptions {
+      profile_id,
+      leaderboard_id,
+      filter,
+      poll_interval_secs: match args.get_i32("poll-interval")? {
+        Some(secs) => secs.max(1) as u64,
+        None => watch::DEFAULT_POLL_INTERVAL_SECS,
+      },
+    };
+    let watched = if is_game_in_progress {
+      Some(most_recent_game.clone())
+    } else {
+      None
+    };
+    actix_rt::System::new("watch").block_on(watch::watch(options, watched))?;
+  }
+
   // for (enemy_profile_id, record) in pt.records.iter() {
   //   match players.get(enemy_profile_id) {
   //     None => continue,
@@ -181,12 +395,146 @@ fn run(player_name_arg: &str, leaderboard_name_arg: &str) -> Result<(), RuntimeE
   Ok(())
 }
 
+struct LobbyArgs {
+  players: Vec<batch::BatchPlayer>,
+  leaderboard_id: fetch::LeaderboardId,
+  filter: match_filter::MatchFilter,
+  concurrency: usize,
+}
+
+// --<command> <leaderboard> <player name or profile id>...
+fn parse_lobby_args(args: &args::Args, command: &str) -> Result<LobbyArgs, RuntimeError> {
+  let leaderboard_name = match args.positional.get(0) {
+    Some(leaderboard_name) => leaderboard_name,
+    None => {
+      return Err(RuntimeError::new(&format!(
+        "Expected `--{} <leaderboard> <players...>`",
+        command
+      )))
+    }
+  };
+  let players: Vec<batch::BatchPlayer> = args
+    .positional
+    .iter()
+    .skip(1)
+    .map(|arg| batch::BatchPlayer::parse(arg))
+    .collect();
+  if players.len() == 0 {
+    return Err(RuntimeError::new("No players given."));
+  }
+  if let Err(e) = server::cache::init_cache() {
+    return Err(RuntimeError::new(&format!(
+      "Could not open the cache: {}",
+      e
+    )));
+  }
+  Ok(LobbyArgs {
+    players,
+    leaderboard_id: format::get_leaderboard_id_from_name(leaderboard_name),
+    filter: match_filter::MatchFilter::from_args(args)?,
+    concurrency: batch::get_concurrency(
+      args
+        .get_i32("concurrency")?
+        .map(|count| count.max(1) as usize),
+    ),
+  })
+}
+
+// looks up every player of a lobby
+fn run_batch(args: &args::Args) -> Result<(), RuntimeError> {
+  let lobby = parse_lobby_args(args, "batch")?;
+  let response = actix_rt::System::new("batch").block_on(async move {
+    server::endpoints::create_batch_response(
+      &lobby.players,
+      lobby.leaderboard_id,
+      &lobby.filter,
+      lobby.concurrency,
+    )
+    .await
+  })?;
+  println!("{}", response.format());
+  Ok(())
+}
+
+// proposes the most even team splits of a lobby
+fn run_balance(args: &args::Args) -> Result<(), RuntimeError> {
+  let lobby = parse_lobby_args(args, "balance")?;
+  let options = balance::BalanceOptions {
+    rival_penalty: args.get_i32("rival-penalty")?.unwrap_or(0) as f64,
+    teammate_bonus: args.get_i32("teammate-bonus")?.unwrap_or(0) as f64,
+    count: args
+      .get_i32("count")?
+      .map(|count| count.max(1) as usize)
+      .unwrap_or(balance::DEFAULT_COUNT),
+  };
+  let splits = actix_rt::System::new("balance").block_on(async move {
+    server::endpoints::create_balance_response(
+      &lobby.players,
+      lobby.leaderboard_id,
+      &lobby.filter,
+      lobby.concurrency,
+      &options,
+    )
+    .await
+  })?;
+  for (i, split) in splits.iter().enumerate() {
+    println!("Split {}: {}", i + 1, split.format());
+  }
+  Ok(())
+}
+
+// shows a single match with all its players
+fn run_match(args: &args::Args) -> Result<(), RuntimeError> {
+  let match_id = match args.get("match") {
+    Some(match_id) if args.positional.len() == 0 => String::from(match_id),
+    _ => return Err(RuntimeError::new("Expected `--match <match_id>`")),
+  };
+  if let Err(e) = server::cache::init_cache() {
+    return Err(RuntimeError::new(&format!(
+      "Could not open the cache: {}",
+      e
+    )));
+  }
+  let detail = actix_rt::System::new("match")
+    .block_on(async move { server::endpoints::create_match_response(&match_id).await })?;
+  print!("{}", detail.format());
+  Ok(())
+}
+
+// the commands other than looking up one player are options, so that no player name is mistaken
+// for one. none if no command was given
+fn run_command(args: &args::Args) -> Option<Result<(), RuntimeError>> {
+  if args.get("match").is_some() {
+    return Some(run_match(args));
+  }
+  match args.get_bool("batch") {
+    Ok(true) => return Some(run_batch(args)),
+    Ok(false) => {}
+    Err(e) => return Some(Err(e)),
+  }
+  match args.get_bool("balance") {
+    Ok(true) => Some(run_balance(args)),
+    Ok(false) => None,
+    Err(e) => Some(Err(e)),
+  }
+}
+
 fn main() {
-  let args: Vec<String> = env::args().collect();
+  let args = args::Args::parse(&env::args().skip(1).collect::<Vec<String>>(), &FLAGS);
+  let subcommand_result = run_command(&args);
+  if let Some(result) = subcommand_result {
+    std::process::exit(match result {
+      Ok(_) => 0,
+      Err(err) => {
+        eprintln!("error: {:?}", err);
+        1
+      }
+    });
+  }
   let mut player_name: String = String::from("");
   let mut leaderboard_name: String = String::from("");
-  let is_error = match args.len() {
-    1 => {
+  let is_error = match args.positional.len() {
+    0 => {
       println!("No args given, running as web server.`");
       std::process::exit(match server::listen() {
         Ok(_) => 0,
@@ -196,17 +544,19 @@ fn main() {
         }
       })
     }
-    2 => {
-      player_name = String::from(&args[1]);
+    1 => {
+      player_name = String::from(&args.positional[0]);
       false
     }
-    3 => {
-      player_name = String::from(&args[1]);
-      leaderboard_name = String::from(&args[2]);
+    2 => {
+      player_name = String::from(&args.positional[0]);
+      leaderboard_name = String::from(&args.positional[1]);
       false
     }
     _ => {
-      println!("Invalid number of args given, expected `<player_name> <?leaderboard_id>`");
+      println!(
+        "Invalid number of args given, expected `<player_name> <?leaderboard_id> [--since <yyyy-mm-dd>] [--until <yyyy-mm-dd>] [--last <n>] [--version <version>] [--min-version <version>] [--ranked] [--team-size <n>] [--map <map_type>] [--timezone <zone or hours>] [--session-gap <minutes>] [--rivals-min-games <n>] [--rivals-count <n>] [--clan-patterns <patterns>] [--clan-roster <tag>] [--compare <profile_id>] [--watch] [--poll-interval <secs>]` or `--batch <leaderboard> <player_name or profile_id>... [--concurrency <n>]` or `--balance <leaderboard> <player_name or profile_id>... [--rival-penalty <elo>] [--teammate-bonus <elo>] [--count <n>]` or `--match <match_id>`"
+      );
       true
     }
   };
@@ -217,7 +567,7 @@ fn main() {
   }
 
   println!("Program started");
-  std::process::exit(match run(&player_name, &leaderboard_name) {
+  std::process::exit(match run(&player_name, &leaderboard_name, &args) {
     Ok(_) => 0,
     Err(err) => {
       eprintln!("error: {:?}", err);
@@ -0,0 +1,177 @@
+use crate::duration;
+use crate::fetch;
+use crate::format;
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchSource {
+  Cache,
+  Aoe2net,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct MatchPlayer {
+  pub profile_id: i32,
+  pub name: String,
+  pub rating: Option<i32>,
+  pub civ: Option<i32>,
+  pub civ_name: Option<String>,
+  pub color: Option<i32>,
+  pub won: Option<bool>,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct MatchTeam {
+  // none for players without a team, who are each on a team of their own
+  pub team: Option<i32>,
+  pub average_rating: Option<f64>,
+  pub won: Option<bool>,
+  pub players: Vec<MatchPlayer>,
+}
+
+impl MatchTeam {
+  fn format(&self) -> String {
+    let title = match self.team {
+      Some(team) => format!("Team {}", team),
+      None => String::from("No team"),
+    };
+    let average_rating = match self.average_rating {
+      Some(average_rating) => format!(" (average elo {:.0})", average_rating),
+      None => String::default(),
+    };
+    let result = match self.won {
+      Some(true) => ", won",
+      Some(false) => ", lost",
+      None => "",
+    };
+    let mut ret = format!("{}{}{}\n", title, average_rating, result);
+    for player in &self.players {
+      ret += &format!(
+        "  {} ({}) {}\n",
+        player.name,
+        player
+          .rating
+          .map_or(String::from("unknown elo"), |rating| rating.to_string()),
+        player.civ_name.clone().unwrap_or(
+          player
+            .civ
+            .map_or(String::from("unknown civ"), |civ| format!("civ {}", civ))
+        )
+      );
+    }
+    return ret;
+  }
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct MatchDetail {
+  pub match_id: String,
+  pub started: i64,
+  pub finished: Option<i64>,
+  pub duration_secs: Option<i64>,
+  pub is_in_progress: bool,
+  pub ranked: bool,
+  pub leaderboard_id: Option<i32>,
+  pub format: String,
+  pub map_type: Option<i32>,
+  pub map_name: Option<String>,
+  pub version: Option<String>,
+  pub teams: Vec<MatchTeam>,
+  pub source: MatchSource,
+}
+
+// a team won if any of its players is reported to have won, and lost if all of them lost
+fn get_team_result(players: &Vec<&fetch::MatchHistoryPlayerResponse>) -> Option<bool> {
+  if players.iter().any(|player| player.won == Some(true)) {
+    Some(true)
+  } else if players.iter().all(|player| player.won == Some(false)) {
+    Some(false)
+  } else {
+    None
+  }
+}
+
+fn get_average_rating(players: &Vec<&fetch::MatchHistoryPlayerResponse>) -> Option<f64> {
+  let ratings: Vec<i32> = players.iter().filter_map(|player| player.rating).collect();
+  if ratings.len() == 0 {
+    return None;
+  }
+  Some(ratings.iter().sum::<i32>() as f64 / ratings.len() as f64)
+}
+
+impl MatchDetail {
+  // the strings are only used for the names of civs and maps, which are left out without them
+  pub fn new(
+    game: &fetch::MatchHistoryGameResponse,
+    strings: Option<&fetch::StringsResponse>,
+    source: MatchSource,
+  ) -> MatchDetail {
+    let teams = game
+      .get_teams()
+      .iter()
+      .map(|players| MatchTeam {
+        team: players.get(0).and_then(|player| player.get_team_id()),
+        average_rating: get_average_rating(players),
+        won: get_team_result(players),
+        players: players
+          .iter()
+          .map(|player| MatchPlayer {
+            profile_id: player.get_profile_id(),
+            name: player.get_name(),
+            rating: player.rating,
+            civ: player.civ,
+            civ_name: match (strings, player.civ) {
+              (Some(strings), Some(civ)) => strings.get_civ_name(civ),
+              _ => None,
+            },
+            color: player.color,
+            won: player.won,
+          })
+          .collect(),
+      })
+      .collect();
+    MatchDetail {
+      match_id: String::from(game.get_match_id()),
+      started: game.started,
+      finished: game.finished.map(|finished| finished as i64),
+      duration_secs: game.get_duration_secs(),
+      is_in_progress: game.finished.is_none(),
+      ranked: game.ranked,
+      leaderboard_id: game.leaderboard_id,
+      format: game.get_format(),
+      map_type: game.map_type,
+      map_name: match (strings, game.map_type) {
+        (Some(strings), Some(map_type)) => strings.get_map_name(map_type),
+        _ => None,
+      },
+      version: game.version.clone(),
+      teams,
+      source,
+    }
+  }
+  pub fn format(&self) -> String {
+    let map = match (&self.map_name, self.map_type) {
+      (Some(map_name), _) => map_name.clone(),
+      (None, Some(map_type)) => format!("map {}", map_type),
+      (None, None) => String::from("unknown map"),
+    };
+    let duration = match self.duration_secs {
+      Some(duration_secs) => duration::format_duration(duration_secs),
+      None if self.is_in_progress => String::from("in progress"),
+      None => String::from("unknown duration"),
+    };
+    let mut ret = format!(
+      "Match {}: {} {} on {}, {}, {}\n",
+      self.match_id,
+      if self.ranked { "ranked" } else { "unranked" },
+      self.format,
+      map,
+      format::timestamp_to_date(self.started),
+      duration
+    );
+    for team in &self.teams {
+      ret += &team.format();
+    }
+    return ret;
+  }
+}
@@ -0,0 +1,160 @@
+extern crate chrono;
+
+use chrono::prelude::*;
+
+use crate::args;
+use crate::error::RuntimeError;
+use crate::fetch;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct MatchFilter {
+  pub since: Option<i64>,
+  pub until: Option<i64>,
+  pub last: Option<usize>,
+  pub version: Option<String>,
+  pub min_version: Option<i64>,
+  pub ranked_only: bool,
+  pub team_size: Option<i32>,
+  pub map_type: Option<i32>,
+}
+
+fn parse_date(name: &str, date: &str) -> Result<NaiveDate, RuntimeError> {
+  match NaiveDate::parse_from_str(date, DATE_FORMAT) {
+    Ok(d) => Ok(d),
+    Err(_) => Err(RuntimeError::new(&format!(
+      "Expected a date like 2020-05-31 for {}, got '{}'",
+      name, date
+    ))),
+  }
+}
+
+// since is the start of the given day and until is the end of it, both in UTC
+pub fn parse_since(date: &str) -> Result<i64, RuntimeError> {
+  Ok(parse_date("since", date)?.and_hms(0, 0, 0).timestamp())
+}
+
+pub fn parse_until(date: &str) -> Result<i64, RuntimeError> {
+  Ok(parse_date("until", date)?.and_hms(23, 59, 59).timestamp())
+}
+
+fn version_number(version: &str) -> Option<i64> {
+  version.trim().parse().ok()
+}
+
+impl MatchFilter {
+  pub fn from_args(args: &args::Args) -> Result<MatchFilter, RuntimeError> {
+    Ok(MatchFilter {
+      since: match args.get("since") {
+        Some(date) => Some(parse_since(date)?),
+        None => None,
+      },
+      until: match args.get("until") {
+        Some(date) => Some(parse_until(date)?),
+        None => None,
+      },
+      last: args.get_i32("last")?.map(|last| last.max(0) as usize),
+      version: args.get("version").map(String::from),
+      min_version: args.get_i32("min-version")?.map(|v| v as i64),
+      ranked_only: args.get_bool("ranked")?,
+      team_size: args.get_i32("team-size")?,
+      map_type: args.get_i32("map")?,
+    })
+  }
+  pub fn is_empty(&self) -> bool {
+    self.since.is_none()
+      && self.until.is_none()
+      && self.last.is_none()
+      && self.version.is_none()
+      && self.min_version.is_none()
+      && !self.ranked_only
+      && self.team_size.is_none()
+      && self.map_type.is_none()
+  }
+  pub fn matches(&self, game: &fetch::MatchHistoryGameResponse) -> bool {
+    if let Some(since) = self.since {
+      if game.started < since {
+        return false;
+      }
+    }
+    if let Some(until) = self.until {
+      if game.started > until {
+        return false;
+      }
+    }
+    if let Some(version) = &self.version {
+      if game.version.as_ref() != Some(version) {
+        return false;
+      }
+    }
+    if let Some(min_version) = self.min_version {
+      match game.version.as_ref().and_then(|v| version_number(v)) {
+        Some(v) if v >= min_version => (),
+        _ => return false,
+      }
+    }
+    if self.ranked_only && !game.ranked {
+      return false;
+    }
+    if let Some(team_size) = self.team_size {
+      if game.get_team_size() != Some(team_size) {
+        return false;
+      }
+    }
+    if let Some(map_type) = self.map_type {
+      if game.map_type != Some(map_type) {
+        return false;
+      }
+    }
+    true
+  }
+  // the indices of the games apply would keep, newest first like the match history
+  pub fn get_matching_indices(
+    &self,
+    match_history: &Vec<fetch::MatchHistoryGameResponse>,
+  ) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..match_history.len())
+      .filter(|i| self.matches(&match_history[*i]))
+      .collect();
+    if let Some(last) = self.last {
+      indices.truncate(last);
+    }
+    return indices;
+  }
+  // match history is sorted newest first, so `last` keeps the most recent games
+  pub fn apply(&self, match_history: &mut Vec<fetch::MatchHistoryGameResponse>) {
+    match_history.retain(|game| self.matches(game));
+    if let Some(last) = self.last {
+      match_history.truncate(last);
+    }
+  }
+  pub fn describe(&self) -> String {
+    let mut parts: Vec<String> = vec![];
+    if let Some(since) = self.since {
+      parts.push(format!("since {}", crate::format::timestamp_to_date(since)));
+    }
+    if let Some(until) = self.until {
+      parts.push(format!("until {}", crate::format::timestamp_to_date(until)));
+    }
+    if let Some(version) = &self.version {
+      parts.push(format!("version {}", version));
+    }
+    if let Some(min_version) = self.min_version {
+      parts.push(format!("version {} or newer", min_version));
+    }
+    if self.ranked_only {
+      parts.push(String::from("ranked only"));
+    }
+    if let Some(team_size) = self.team_size {
+      parts.push(format!("{}v{} only", team_size, team_size));
+    }
+    if let Some(map_type) = self.map_type {
+      parts.push(format!("map {}", map_type));
+    }
+    if let Some(last) = self.last {
+      parts.push(format!("last {} games", last));
+    }
+    return parts.join(", ");
+  }
+}
@@ -0,0 +1,243 @@
+use crate::fetch;
+use crate::match_filter;
+
+const REPORTED_CONFIDENCE: f64 = 1.0;
+const RATING_HISTORY_CONFIDENCE: f64 = 0.95;
+const MATCH_PLAYERS_CONFIDENCE: f64 = 0.9;
+const LATER_GAME_CONFIDENCE: f64 = 0.75;
+
+// ratings can drift between games that are far apart, so a later game says less about the result
+const STALE_GAME_SECS: i64 = 14 * 24 * 60 * 60;
+const STALE_GAME_CONFIDENCE: f64 = 0.6;
+
+// the tracked player's own rating change counts for more than the other players in the game,
+// who may have played games in between that are not in this match history
+const MY_RATING_WEIGHT: f64 = 2.0;
+const OTHER_RATING_WEIGHT: f64 = 1.0;
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Provenance {
+  Reported,
+  MatchPlayers,
+  RatingHistory,
+  LaterGame,
+  Unknown,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct InferredOutcome {
+  pub match_id: String,
+  pub started: i64,
+  pub is_win: Option<bool>,
+  pub is_in_progress: bool,
+  pub provenance: Provenance,
+  pub confidence: f64,
+}
+
+impl InferredOutcome {
+  fn new(
+    game: &fetch::MatchHistoryGameResponse,
+    is_win: Option<bool>,
+    provenance: Provenance,
+    confidence: f64,
+  ) -> InferredOutcome {
+    InferredOutcome {
+      match_id: String::from(game.get_match_id()),
+      started: game.started,
+      is_win,
+      is_in_progress: false,
+      provenance,
+      confidence,
+    }
+  }
+  pub fn describe(&self) -> String {
+    let source = match self.provenance {
+      Provenance::Reported => return String::from("reported"),
+      Provenance::Unknown => return String::from("unknown"),
+      Provenance::MatchPlayers => "results of the other players",
+      Provenance::RatingHistory => "rating history",
+      Provenance::LaterGame => "ratings in a later game",
+    };
+    return format!(
+      "inferred from {}, {:.0}% confidence",
+      source,
+      self.confidence * 100.0
+    );
+  }
+}
+
+fn is_missing_result(game: &fetch::MatchHistoryGameResponse, profile_id: i32) -> bool {
+  match game.get_player_by_profile_id(profile_id) {
+    Some(player) => player.won.is_none(),
+    None => false,
+  }
+}
+
+// the most recent game is only missing a result because it has not finished yet. only the games
+// the filter counts need a result
+pub fn needs_rating_history(
+  match_history: &Vec<fetch::MatchHistoryGameResponse>,
+  profile_id: i32,
+  filter: &match_filter::MatchFilter,
+) -> bool {
+  filter
+    .get_matching_indices(match_history)
+    .into_iter()
+    .any(|i| {
+      let game = &match_history[i];
+      is_missing_result(game, profile_id) && !(i == 0 && game.finished.is_none())
+    })
+}
+
+fn from_match_players(game: &fetch::MatchHistoryGameResponse, profile_id: i32) -> Option<bool> {
+  for ally_player in game.get_my_team(profile_id) {
+    if let Some(won) = ally_player.won {
+      return Some(won);
+    }
+  }
+  // only one team wins, but a loss for one of several other teams says nothing about mine
+  let is_two_teams = game.get_team_count() == 2;
+  for enemy_player in game.get_opposing_team(profile_id) {
+    match enemy_player.won {
+      Some(true) => return Some(false),
+      Some(false) if is_two_teams => return Some(true),
+      _ => (),
+    }
+  }
+  None
+}
+
+fn from_rating_history(
+  game: &fetch::MatchHistoryGameResponse,
+  next_started: Option<i64>,
+  rating_history: &Vec<fetch::RatingHistoryResponse>,
+) -> Option<bool> {
+  let before = rating_history
+    .iter()
+    .filter(|entry| entry.timestamp <= game.started)
+    .max_by_key(|entry| entry.timestamp)?;
+  let after = rating_history
+    .iter()
+    .filter(|entry| entry.timestamp > game.started)
+    .min_by_key(|entry| entry.timestamp)?;
+  // an entry after the next game started may belong to that game instead
+  if let Some(next_started) = next_started {
+    if after.timestamp > next_started {
+      return None;
+    }
+  }
+  let won = after.num_wins > before.num_wins;
+  let lost = after.num_losses > before.num_losses;
+  if won == lost {
+    None
+  } else {
+    Some(won)
+  }
+}
+
+fn from_later_game(
+  game: &fetch::MatchHistoryGameResponse,
+  later_game: &fetch::MatchHistoryGameResponse,
+  profile_id: i32,
+) -> Option<f64> {
+  let mut votes = 0.0;
+  let mut total_weight = 0.0;
+  let my_team_id = game.get_team_id_by_profile_id(profile_id);
+  for player in &game.players {
+    let later_player = match later_game.get_player_by_profile_id(player.get_profile_id()) {
+      Some(p) => p,
+      None => continue,
+    };
+    let (rating, later_rating) = match (player.rating, later_player.rating) {
+      (Some(r), Some(lr)) => (r, lr),
+      _ => continue,
+    };
+    let weight = if player.get_profile_id() == profile_id {
+      MY_RATING_WEIGHT
+    } else if my_team_id.is_some() && player.get_team_id() == my_team_id {
+      OTHER_RATING_WEIGHT
+    } else if game.get_team_count() == 2 {
+      -OTHER_RATING_WEIGHT
+    } else {
+      continue;
+    };
+    total_weight += weight.abs();
+    if later_rating > rating {
+      votes += weight;
+    } else if later_rating < rating {
+      votes -= weight;
+    }
+  }
+  if total_weight == 0.0 || votes == 0.0 {
+    return None;
+  }
+  // positive for a win, negative for a loss, scaled by how much the players agree
+  Some(votes / total_weight)
+}
+
+pub fn infer_outcome(
+  match_history: &Vec<fetch::MatchHistoryGameResponse>,
+  index: usize,
+  profile_id: i32,
+  rating_history: &Vec<fetch::RatingHistoryResponse>,
+) -> InferredOutcome {
+  let game = &match_history[index];
+  let player = match game.get_player_by_profile_id(profile_id) {
+    Some(p) => p,
+    None => return InferredOutcome::new(game, None, Provenance::Unknown, 0.0),
+  };
+
+  if let Some(won) = player.won {
+    return InferredOutcome::new(game, Some(won), Provenance::Reported, REPORTED_CONFIDENCE);
+  }
+
+  if let Some(won) = from_match_players(game, profile_id) {
+    return InferredOutcome::new(
+      game,
+      Some(won),
+      Provenance::MatchPlayers,
+      MATCH_PLAYERS_CONFIDENCE,
+    );
+  }
+
+  if index == 0 && game.finished.is_none() {
+    let mut outcome = InferredOutcome::new(game, None, Provenance::Unknown, 0.0);
+    outcome.is_in_progress = true;
+    return outcome;
+  }
+
+  // match history is sorted newest first, so later games have a lower index
+  let later_game = match_history[..index].iter().rev().find(|later_game| {
+    later_game.leaderboard_id == game.leaderboard_id
+      && later_game.get_player_by_profile_id(profile_id).is_some()
+  });
+  let next_started = later_game.map(|later_game| later_game.started);
+
+  if let Some(won) = from_rating_history(game, next_started, rating_history) {
+    return InferredOutcome::new(
+      game,
+      Some(won),
+      Provenance::RatingHistory,
+      RATING_HISTORY_CONFIDENCE,
+    );
+  }
+
+  if let Some(later_game) = later_game {
+    if let Some(agreement) = from_later_game(game, later_game, profile_id) {
+      let confidence = if later_game.started - game.started > STALE_GAME_SECS {
+        STALE_GAME_CONFIDENCE
+      } else {
+        LATER_GAME_CONFIDENCE
+      };
+      return InferredOutcome::new(
+        game,
+        Some(agreement > 0.0),
+        Provenance::LaterGame,
+        confidence * agreement.abs(),
+      );
+    }
+  }
+
+  InferredOutcome::new(game, None, Provenance::Unknown, 0.0)
+}
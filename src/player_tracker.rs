@@ -1,4 +1,9 @@
+use crate::aliases;
+use crate::duration;
 use crate::fetch;
+use crate::match_filter;
+use crate::outcome;
+use crate::rating;
 use serde::ser::{Serialize, Serializer, SerializeStruct};
 
 pub struct Record {
@@ -7,6 +12,53 @@ pub struct Record {
   pub losses_to: i32,
   pub games: Vec<fetch::MatchHistoryGameResponse>,
   pub last_played_against: String,
+  pub performance: rating::Performance,
+  pub formats: std::collections::BTreeMap<String, FormatRecord>,
+  pub durations: duration::DurationStats,
+  pub ratings: Vec<EncounterRating>,
+}
+
+// ratings as they were in one game against the opponent
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct EncounterRating {
+  pub match_id: String,
+  pub started: i64,
+  pub opponent_rating: Option<i32>,
+  pub my_rating: Option<i32>,
+  // my rating minus the opponent's rating
+  pub rating_difference: Option<i32>,
+}
+
+impl EncounterRating {
+  fn from_game(
+    game: &fetch::MatchHistoryGameResponse,
+    my_profile_id: i32,
+    other_profile_id: i32,
+  ) -> EncounterRating {
+    let opponent_rating = game
+      .get_player_by_profile_id(other_profile_id)
+      .and_then(|player| player.rating);
+    let my_rating = game
+      .get_player_by_profile_id(my_profile_id)
+      .and_then(|player| player.rating);
+    EncounterRating {
+      match_id: String::from(game.get_match_id()),
+      started: game.started,
+      opponent_rating,
+      my_rating,
+      rating_difference: match (my_rating, opponent_rating) {
+        (Some(mine), Some(theirs)) => Some(mine - theirs),
+        _ => None,
+      },
+    }
+  }
+}
+
+fn average(values: &Vec<i32>) -> Option<f64> {
+  if values.len() == 0 {
+    return None;
+  }
+  Some(values.iter().sum::<i32>() as f64 / values.len() as f64)
 }
 
 impl Serialize for Record {
@@ -14,16 +66,83 @@ impl Serialize for Record {
   where
     S: Serializer,
   {
-    let mut state = serializer.serialize_struct("Record", 4)?;
+    // the rating in every game is only sent with the records page, see record_page
+    let mut state = serializer.serialize_struct("Record", 11)?;
     state.serialize_field("profile_id", &self.profile_id)?;
     state.serialize_field("wins_against", &self.wins_against)?;
     state.serialize_field("losses_to", &self.losses_to)?;
     state.serialize_field("last_played_against", &self.last_played_against)?;
+    state.serialize_field("performance", &self.performance)?;
+    state.serialize_field("formats", &self.formats)?;
+    state.serialize_field("durations", &self.durations)?;
+    state.serialize_field(
+      "average_opponent_rating",
+      &self.get_average_opponent_rating(),
+    )?;
+    state.serialize_field("min_opponent_rating", &self.get_min_opponent_rating())?;
+    state.serialize_field("max_opponent_rating", &self.get_max_opponent_rating())?;
+    state.serialize_field(
+      "average_rating_difference",
+      &self.get_average_rating_difference(),
+    )?;
     state.end()
   }
 }
 
 impl Record {
+  fn get_opponent_ratings(&self) -> Vec<i32> {
+    self
+      .ratings
+      .iter()
+      .filter_map(|encounter| encounter.opponent_rating)
+      .collect()
+  }
+  pub fn get_average_opponent_rating(&self) -> Option<f64> {
+    average(&self.get_opponent_ratings())
+  }
+  pub fn get_min_opponent_rating(&self) -> Option<i32> {
+    self.get_opponent_ratings().into_iter().min()
+  }
+  pub fn get_max_opponent_rating(&self) -> Option<i32> {
+    self.get_opponent_ratings().into_iter().max()
+  }
+  pub fn get_average_rating(&self) -> Option<f64> {
+    average(
+      &self
+        .ratings
+        .iter()
+        .filter_map(|encounter| encounter.my_rating)
+        .collect(),
+    )
+  }
+  pub fn get_average_rating_difference(&self) -> Option<f64> {
+    average(
+      &self
+        .ratings
+        .iter()
+        .filter_map(|encounter| encounter.rating_difference)
+        .collect(),
+    )
+  }
+  pub fn format_ratings(&self) -> String {
+    match (
+      self.get_average_opponent_rating(),
+      self.get_min_opponent_rating(),
+      self.get_max_opponent_rating(),
+    ) {
+      (Some(average_rating), Some(min_rating), Some(max_rating)) => {
+        let difference = match self.get_average_rating_difference() {
+          Some(difference) => format!(", my rating {:+.0} on average", difference),
+          None => String::default(),
+        };
+        format!(
+          "average {:.0} (min {}, max {}){}",
+          average_rating, min_rating, max_rating, difference
+        )
+      }
+      _ => String::default(),
+    }
+  }
   pub fn format(&self, player: &fetch::MatchHistoryPlayerResponse) -> String {
     let game = match self.games.get(self.games.len() - 1) {
       Some(g) => g,
@@ -31,24 +150,86 @@ impl Record {
     };
     let player_name = player.get_name().replace(",", "");
     return format!(
-      "{profile_id},{player_name},{num_games},{wins_against},{losses_to},{elo},{date}",
+      "{profile_id},{player_name},{num_games},{wins_against},{losses_to},{elo},{date},{expected_wins:.2},{wins_above_expected:.2},{average_elo},{min_elo},{max_elo},{average_elo_difference}",
       profile_id = player.get_profile_id(),
       player_name = player_name,
       num_games = self.games.len(),
       wins_against = self.wins_against,
       losses_to = self.losses_to,
       elo = player.get_rating(),
-      date = crate::format::timestamp_to_date(game.started)
+      date = crate::format::timestamp_to_date(game.started),
+      expected_wins = self.performance.expected_wins,
+      wins_above_expected = self.performance.wins_above_expected(),
+      average_elo = format_optional(self.get_average_opponent_rating().map(|r| r.round())),
+      min_elo = format_optional(self.get_min_opponent_rating()),
+      max_elo = format_optional(self.get_max_opponent_rating()),
+      average_elo_difference =
+        format_optional(self.get_average_rating_difference().map(|r| r.round()))
     );
   }
 }
 
+fn format_optional<T: std::fmt::Display>(value: Option<T>) -> String {
+  match value {
+    Some(value) => value.to_string(),
+    None => String::default(),
+  }
+}
+
+#[derive(serde::Serialize, Debug, Clone, Default)]
+pub struct TeammateRecord {
+  pub profile_id: i32,
+  pub wins_with: i32,
+  pub losses_with: i32,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Default)]
+pub struct FormatRecord {
+  pub wins: i32,
+  pub losses: i32,
+}
+
+pub fn add_format_result(
+  formats: &mut std::collections::BTreeMap<String, FormatRecord>,
+  game: &fetch::MatchHistoryGameResponse,
+  is_win: bool,
+) {
+  let format_record = formats.entry(game.get_format()).or_default();
+  if is_win {
+    format_record.wins += 1;
+  } else {
+    format_record.losses += 1;
+  }
+}
+
+pub fn format_breakdown(formats: &std::collections::BTreeMap<String, FormatRecord>) -> String {
+  let parts: Vec<String> = formats
+    .iter()
+    .map(|(format, format_record)| {
+      format!("{} {}-{}", format, format_record.wins, format_record.losses)
+    })
+    .collect();
+  return parts.join(", ");
+}
+
 pub struct PlayerTracker {
   pub profile_id: i32,
   pub wins: i32,
   pub losses: i32,
+  pub ffa_wins: i32,
+  pub ffa_losses: i32,
+  pub formats: std::collections::BTreeMap<String, FormatRecord>,
+  pub durations: duration::DurationStats,
   pub records: std::collections::HashMap<i32, Record>,
+  pub teammates: std::collections::HashMap<i32, TeammateRecord>,
   pub players: std::collections::HashMap<i32, fetch::MatchHistoryPlayerResponse>,
+  // every name seen for each profile id, players only holds the latest one
+  pub names: std::collections::HashMap<i32, Vec<aliases::NameSeen>>,
+  pub performance: rating::Performance,
+  pub expectations: Vec<rating::MatchExpectation>,
+  pub outcomes: Vec<outcome::InferredOutcome>,
+  // the index in outcomes of each match id, outcomes are looked up once per game in most analyses
+  outcome_indices: std::collections::HashMap<String, usize>,
 }
 
 impl Serialize for PlayerTracker {
@@ -56,12 +237,21 @@ impl Serialize for PlayerTracker {
   where
     S: Serializer,
   {
-    let mut state = serializer.serialize_struct("PlayerTracker", 5)?;
+    let mut state = serializer.serialize_struct("PlayerTracker", 14)?;
     state.serialize_field("profile_id", &self.profile_id)?;
     state.serialize_field("wins", &self.wins)?;
     state.serialize_field("losses", &self.losses)?;
+    state.serialize_field("ffa_wins", &self.ffa_wins)?;
+    state.serialize_field("ffa_losses", &self.ffa_losses)?;
+    state.serialize_field("formats", &self.formats)?;
+    state.serialize_field("durations", &self.durations)?;
     state.serialize_field("records", &self.records)?;
+    state.serialize_field("teammates", &self.teammates)?;
     state.serialize_field("players", &self.players)?;
+    state.serialize_field("aliases", &self.get_all_aliases())?;
+    state.serialize_field("performance", &self.performance)?;
+    state.serialize_field("upsets", &self.get_upsets())?;
+    state.serialize_field("inferred_outcomes", &self.get_inferred_outcomes())?;
     state.end()
   }
 }
@@ -72,58 +262,70 @@ impl PlayerTracker {
       profile_id,
       wins: 0,
       losses: 0,
+      ffa_wins: 0,
+      ffa_losses: 0,
+      formats: std::collections::BTreeMap::new(),
+      durations: duration::DurationStats::default(),
       records: std::collections::HashMap::new(),
+      teammates: std::collections::HashMap::new(),
       players: std::collections::HashMap::new(),
+      names: std::collections::HashMap::new(),
+      performance: rating::Performance::default(),
+      expectations: vec![],
+      outcomes: vec![],
+      outcome_indices: std::collections::HashMap::new(),
     }
   }
-  pub fn track_players(&mut self, match_history: &std::vec::Vec<fetch::MatchHistoryGameResponse>) {
-    println!("Processing match history: {} games", match_history.len());
-
-    for i in (1..match_history.len()).rev() {
-      let game = &match_history[i];
-      let future_game = &match_history[i - 1];
-      self.process_match(game, future_game);
-    }
+  // match_history is the whole history on the leaderboard, outcomes are inferred from the games
+  // around each one whether the filter counts them or not
+  pub fn track_players(
+    &mut self,
+    match_history: &std::vec::Vec<fetch::MatchHistoryGameResponse>,
+    rating_history: &std::vec::Vec<fetch::RatingHistoryResponse>,
+    filter: &match_filter::MatchFilter,
+  ) {
+    let counted = filter.get_matching_indices(match_history);
+    println!("Processing match history: {} games", counted.len());
 
-    if match_history.len() > 0 {
-      let most_recent_game = &match_history[0];
-      self.process_match(most_recent_game, most_recent_game);
+    for i in counted.into_iter().rev() {
+      let outcome = outcome::infer_outcome(match_history, i, self.profile_id, rating_history);
+      self.process_match(&match_history[i], &outcome);
+      self
+        .outcome_indices
+        .insert(outcome.match_id.clone(), self.outcomes.len());
+      self.outcomes.push(outcome);
     }
   }
   pub fn process_match(
     &mut self,
     game: &fetch::MatchHistoryGameResponse,
-    future_game: &fetch::MatchHistoryGameResponse,
+    outcome: &outcome::InferredOutcome,
   ) {
-    let player = game.get_player_by_profile_id(self.profile_id).unwrap();
-    let mut is_game_in_progress = false;
-    let is_win = match player.won {
+    for player in &game.players {
+      let name = player.get_name();
+      if name != "" {
+        aliases::add_name(
+          self.names.entry(player.get_profile_id()).or_default(),
+          &name,
+          game.started,
+        );
+      }
+    }
+
+    let is_game_in_progress = outcome.is_in_progress;
+    let is_win = match outcome.is_win {
       Some(is_win) => is_win,
+      None if is_game_in_progress => false,
       None => {
-        let player_in_future_game = future_game
-          .get_player_by_profile_id(self.profile_id)
-          .unwrap_or(player);
-        let future_rating = player_in_future_game.get_rating();
-        let rating = player.get_rating();
-        if rating > future_rating {
-          false
-        } else if rating < future_rating {
-          true
-        } else {
-          if game.get_match_id() == future_game.get_match_id() {
-            is_game_in_progress = true;
-            false
-          } else {
-            println!(
-              "Disregarding incomplete game future_game_id={} future_rating={} game_id={} rating={}",
-              future_game.get_match_id(), future_rating, game.get_match_id(), rating
-            );
-            return;
-          }
-        }
+        println!(
+          "Disregarding game with unknown result game_id={}",
+          game.get_match_id()
+        );
+        return;
       }
     };
 
+    let mut expectation: Option<rating::MatchExpectation> = None;
     if is_game_in_progress {
       println!("Game in progress!");
     } else {
@@ -132,25 +334,45 @@ impl PlayerTracker {
       } else {
         self.losses += 1;
       }
-    }
-
-    let other_team_profile_ids = game.get_opposing_team_profile_ids(self.profile_id);
-    for profile_id in &other_team_profile_ids {
-      if is_game_in_progress {
-        self.add_current_game_record(*profile_id, game);
-      } else {
-        self.add_record(*profile_id, is_win, game);
+      if game.is_free_for_all() {
+        if is_win {
+          self.ffa_wins += 1;
+        } else {
+          self.ffa_losses += 1;
+        }
+      }
+      add_format_result(&mut self.formats, game, is_win);
+      self.durations.add(game, Some(is_win));
+      expectation = rating::MatchExpectation::from_game(game, self.profile_id, is_win);
+      if let Some(expectation) = &expectation {
+        self.performance.add(expectation);
+        self.expectations.push(expectation.clone());
       }
     }
 
     let other_team = game.get_opposing_team(self.profile_id);
     for &enemy_player in &other_team {
+      let profile_id = enemy_player.get_profile_id();
+      let result_against = if is_game_in_progress {
+        None
+      } else {
+        game.get_result_against(is_win, enemy_player)
+      };
+      match result_against {
+        Some(is_win_against) => {
+          self.add_record(profile_id, is_win_against, game, expectation.as_ref())
+        }
+        None => self.add_record_without_result(profile_id, game),
+      }
       self
         .players
         .insert(enemy_player.get_profile_id(), enemy_player.clone());
     }
     let my_team = game.get_my_team(self.profile_id);
     for &ally_player in &my_team {
+      if !is_game_in_progress {
+        self.add_teammate_record(ally_player.get_profile_id(), is_win);
+      }
       self
         .players
         .insert(ally_player.get_profile_id(), ally_player.clone());
@@ -162,7 +384,18 @@ impl PlayerTracker {
     other_profile_id: i32,
     is_win: bool,
     game: &fetch::MatchHistoryGameResponse,
+    expectation: Option<&rating::MatchExpectation>,
   ) {
+    let mut performance = rating::Performance::default();
+    if let Some(expectation) = expectation {
+      performance.add(expectation);
+    }
+    let mut formats = std::collections::BTreeMap::new();
+    add_format_result(&mut formats, game, is_win);
+    let mut durations = duration::DurationStats::default();
+    durations.add(game, Some(is_win));
+    let encounter = EncounterRating::from_game(game, self.profile_id, other_profile_id);
+
     let mut win_ctr = 0;
     let mut loss_ctr = 0;
     if is_win {
@@ -183,6 +416,10 @@ impl PlayerTracker {
             losses_to: loss_ctr,
             games: vec![game.clone()],
             last_played_against,
+            performance,
+            formats,
+            durations,
+            ratings: vec![encounter],
           },
         );
         return;
@@ -197,13 +434,23 @@ impl PlayerTracker {
     }
     record.last_played_against = last_played_against;
     record.games.push(game.clone());
+    if let Some(expectation) = expectation {
+      record.performance.add(expectation);
+    }
+    add_format_result(&mut record.formats, game, is_win);
+    record.durations.add(game, Some(is_win));
+    record.ratings.push(encounter);
   }
-  pub fn add_current_game_record(
+  // used for games in progress and for free for all games where neither player won
+  pub fn add_record_without_result(
     &mut self,
     other_profile_id: i32,
     game: &fetch::MatchHistoryGameResponse,
   ) {
     let last_played_against = crate::format::timestamp_to_date(game.started);
+    let mut durations = duration::DurationStats::default();
+    durations.add(game, None);
+    let encounter = EncounterRating::from_game(game, self.profile_id, other_profile_id);
 
     let mut record = match self.records.get_mut(&other_profile_id) {
       None => {
@@ -215,6 +462,10 @@ impl PlayerTracker {
             losses_to: 0,
             games: vec![game.clone()],
             last_played_against,
+            performance: rating::Performance::default(),
+            formats: std::collections::BTreeMap::new(),
+            durations,
+            ratings: vec![encounter],
           },
         );
         return;
@@ -224,6 +475,29 @@ impl PlayerTracker {
 
     record.last_played_against = last_played_against;
     record.games.push(game.clone());
+    record.durations.add(game, None);
+    record.ratings.push(encounter);
+  }
+  pub fn add_teammate_record(&mut self, ally_profile_id: i32, is_win: bool) {
+    let record = self
+      .teammates
+      .entry(ally_profile_id)
+      .or_insert(TeammateRecord {
+        profile_id: ally_profile_id,
+        wins_with: 0,
+        losses_with: 0,
+      });
+    if is_win {
+      record.wins_with += 1;
+    } else {
+      record.losses_with += 1;
+    }
+  }
+  pub fn get_teammate_record(&self, ally_profile_id: i32) -> (i32, i32) {
+    match self.teammates.get(&ally_profile_id) {
+      None => (0, 0),
+      Some(record) => (record.wins_with, record.losses_with),
+    }
   }
   pub fn get_win_loss_record(&self, other_profile_id: i32) -> (i32, i32) {
     match self.records.get(&other_profile_id) {
@@ -231,4 +505,105 @@ impl PlayerTracker {
       Some(record) => (record.wins_against, record.losses_to),
     }
   }
+  // results against the opponent in games with a known result, oldest first
+  pub fn get_results_against(&self, other_profile_id: i32) -> Vec<bool> {
+    let record = match self.records.get(&other_profile_id) {
+      Some(record) => record,
+      None => return vec![],
+    };
+    let mut results: Vec<bool> = vec![];
+    for game in &record.games {
+      let is_win = match self
+        .get_outcome(game.get_match_id())
+        .and_then(|outcome| outcome.is_win)
+      {
+        Some(is_win) => is_win,
+        None => continue,
+      };
+      let opponent = match game.get_player_by_profile_id(other_profile_id) {
+        Some(p) => p,
+        None => continue,
+      };
+      if let Some(is_win_against) = game.get_result_against(is_win, opponent) {
+        results.push(is_win_against);
+      }
+    }
+    return results;
+  }
+  // the number of wins (positive) or losses (negative) in a row against the opponent
+  pub fn get_streak_against(&self, other_profile_id: i32) -> i32 {
+    let results = self.get_results_against(other_profile_id);
+    let last = match results.last() {
+      Some(last) => *last,
+      None => return 0,
+    };
+    let count = results
+      .iter()
+      .rev()
+      .take_while(|is_win| **is_win == last)
+      .count() as i32;
+    if last {
+      count
+    } else {
+      -count
+    }
+  }
+  pub fn get_outcome(&self, match_id: &str) -> Option<&outcome::InferredOutcome> {
+    self
+      .outcome_indices
+      .get(match_id)
+      .map(|i| &self.outcomes[*i])
+  }
+  // every finished game whose result was not reported by aoe2.net
+  pub fn get_inferred_outcomes(&self) -> Vec<&outcome::InferredOutcome> {
+    self
+      .outcomes
+      .iter()
+      .filter(|outcome| {
+        outcome.provenance != outcome::Provenance::Reported && !outcome.is_in_progress
+      })
+      .collect()
+  }
+  // names from other lookups, e.g. the cache database, are merged into the ones seen here
+  pub fn merge_names(&mut self, names: &std::collections::HashMap<i32, Vec<aliases::NameSeen>>) {
+    for (profile_id, other_names) in names {
+      aliases::merge_names(self.names.entry(*profile_id).or_default(), other_names);
+    }
+  }
+  pub fn get_aliases(&self, profile_id: i32) -> Vec<&aliases::NameSeen> {
+    let current_name = match self.players.get(&profile_id) {
+      Some(player) => player.get_name(),
+      None => String::default(),
+    };
+    match self.names.get(&profile_id) {
+      Some(names) => aliases::get_aliases(names, &current_name),
+      None => vec![],
+    }
+  }
+  pub fn format_aliases(&self, profile_id: i32) -> String {
+    let current_name = match self.players.get(&profile_id) {
+      Some(player) => player.get_name(),
+      None => String::default(),
+    };
+    match self.names.get(&profile_id) {
+      Some(names) => aliases::format_aliases(names, &current_name),
+      None => String::default(),
+    }
+  }
+  // only players who have gone by more than one name
+  pub fn get_all_aliases(&self) -> std::collections::HashMap<i32, Vec<&aliases::NameSeen>> {
+    self
+      .names
+      .keys()
+      .map(|profile_id| (*profile_id, self.get_aliases(*profile_id)))
+      .filter(|(_, aliases)| aliases.len() > 0)
+      .collect()
+  }
+  pub fn get_upsets(&self) -> Vec<&rating::MatchExpectation> {
+    self
+      .expectations
+      .iter()
+      .filter(|expectation| expectation.is_upset)
+      .collect()
+  }
 }
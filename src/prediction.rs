@@ -0,0 +1,129 @@
+use crate::fetch;
+use crate::player_tracker;
+use crate::rating;
+
+// how much a head to head or teammate record can move the rating based prediction, in log odds
+const HEAD_TO_HEAD_WEIGHT: f64 = 0.5;
+const TEAMMATE_WEIGHT: f64 = 0.5;
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct PredictionFactor {
+  pub kind: String,
+  pub profile_id: Option<i32>,
+  pub name: String,
+  pub log_odds: f64,
+  pub description: String,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct Prediction {
+  pub match_id: String,
+  pub win_probability: f64,
+  pub factors: Vec<PredictionFactor>,
+}
+
+impl Prediction {
+  pub fn explain(&self) -> String {
+    let mut ret = format!("Predicted win chance: {:.0}%\n", self.win_probability * 100.0);
+    for factor in &self.factors {
+      ret += &format!("  {:+.2} {}\n", factor.log_odds, factor.description);
+    }
+    return ret;
+  }
+}
+
+fn to_log_odds(probability: f64) -> f64 {
+  return (probability / (1.0 - probability)).ln();
+}
+
+fn from_log_odds(log_odds: f64) -> f64 {
+  return 1.0 / (1.0 + (-log_odds).exp());
+}
+
+// one win and one loss are added to every record so that a single game does not dominate
+fn record_log_odds(wins: i32, losses: i32) -> f64 {
+  return ((wins as f64 + 1.0) / (losses as f64 + 1.0)).ln();
+}
+
+pub fn predict_game(
+  game: &fetch::MatchHistoryGameResponse,
+  tracker: &player_tracker::PlayerTracker,
+) -> Prediction {
+  let mut factors: Vec<PredictionFactor> = vec![];
+
+  let allies = game.get_my_team(tracker.profile_id);
+  let mut my_team = allies.clone();
+  let other_team = game.get_opposing_team(tracker.profile_id);
+  if let Some(me) = game.get_player_by_profile_id(tracker.profile_id) {
+    my_team.push(me);
+  }
+  match (
+    rating::team_average_rating(&my_team),
+    rating::team_average_rating(&other_team),
+  ) {
+    (Some(my_rating), Some(other_rating)) if game.get_team_count() == 2 => {
+      let expected = rating::expected_score(my_rating, other_rating);
+      factors.push(PredictionFactor {
+        kind: String::from("rating"),
+        profile_id: None,
+        name: String::from("team ratings"),
+        log_odds: to_log_odds(expected),
+        description: format!(
+          "team rating {:.0} vs {:.0} ({:.0}% expected)",
+          my_rating,
+          other_rating,
+          expected * 100.0
+        ),
+      });
+    }
+    _ => (),
+  }
+
+  for &enemy_player in &other_team {
+    let (wins, losses) = tracker.get_win_loss_record(enemy_player.get_profile_id());
+    if wins + losses == 0 {
+      continue;
+    }
+    factors.push(PredictionFactor {
+      kind: String::from("head_to_head"),
+      profile_id: Some(enemy_player.get_profile_id()),
+      name: enemy_player.get_name(),
+      log_odds: HEAD_TO_HEAD_WEIGHT * record_log_odds(wins, losses) / other_team.len() as f64,
+      description: format!(
+        "{} wins, {} losses against {}",
+        wins,
+        losses,
+        enemy_player.get_name()
+      ),
+    });
+  }
+
+  // synergy is how much better (or worse) the player does with an ally than with anyone
+  let overall_log_odds = record_log_odds(tracker.wins, tracker.losses);
+  for &ally_player in &allies {
+    let (wins, losses) = tracker.get_teammate_record(ally_player.get_profile_id());
+    if wins + losses == 0 {
+      continue;
+    }
+    factors.push(PredictionFactor {
+      kind: String::from("teammate"),
+      profile_id: Some(ally_player.get_profile_id()),
+      name: ally_player.get_name(),
+      log_odds: TEAMMATE_WEIGHT * (record_log_odds(wins, losses) - overall_log_odds)
+        / allies.len() as f64,
+      description: format!(
+        "{} wins, {} losses with {}",
+        wins,
+        losses,
+        ally_player.get_name()
+      ),
+    });
+  }
+
+  let log_odds: f64 = factors.iter().map(|factor| factor.log_odds).sum();
+  Prediction {
+    match_id: String::from(game.get_match_id()),
+    win_probability: from_log_odds(log_odds),
+    factors,
+  }
+}
@@ -0,0 +1,234 @@
+use crate::fetch;
+use crate::player_tracker;
+use crate::session;
+
+// opponents who have been on the same team this many times before look like a premade group
+const OPPONENT_PREMADE_MIN_GAMES: i32 = 2;
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueType {
+  Premade,
+  PartialPremade,
+  Solo,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Default)]
+pub struct QueueRecord {
+  pub games: i32,
+  pub wins: i32,
+  pub losses: i32,
+}
+
+impl QueueRecord {
+  fn add(&mut self, is_win: bool) {
+    self.games += 1;
+    if is_win {
+      self.wins += 1;
+    } else {
+      self.losses += 1;
+    }
+  }
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct PremadeGroup {
+  pub profile_ids: Vec<i32>,
+  pub names: Vec<String>,
+  pub record: QueueRecord,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Default)]
+pub struct PremadeStats {
+  pub premade: QueueRecord,
+  pub partial_premade: QueueRecord,
+  pub solo: QueueRecord,
+  pub groups: Vec<PremadeGroup>,
+}
+
+impl PremadeStats {
+  pub fn format(&self) -> String {
+    let mut ret = format!(
+      "premade {}-{}, partly premade {}-{}, solo queue {}-{}\n",
+      self.premade.wins,
+      self.premade.losses,
+      self.partial_premade.wins,
+      self.partial_premade.losses,
+      self.solo.wins,
+      self.solo.losses
+    );
+    for group in &self.groups {
+      ret += &format!(
+        "  with {}: {}-{}\n",
+        group.names.join(", "),
+        group.record.wins,
+        group.record.losses
+      );
+    }
+    return ret;
+  }
+}
+
+// an ally is part of a premade when they were also on the tracked player's team in another game
+// of the same session
+pub fn classify_sessions(
+  match_history: &Vec<fetch::MatchHistoryGameResponse>,
+  profile_id: i32,
+  sessions: &Vec<session::Session>,
+) -> std::collections::HashMap<String, QueueType> {
+  let mut ret = std::collections::HashMap::new();
+  for session in sessions {
+    let games: Vec<&fetch::MatchHistoryGameResponse> = match_history
+      .iter()
+      .filter(|game| session.match_ids.iter().any(|id| id == game.get_match_id()))
+      .collect();
+    let mut ally_games: std::collections::HashMap<i32, i32> = std::collections::HashMap::new();
+    for game in &games {
+      for ally_player in game.get_my_team(profile_id) {
+        *ally_games.entry(ally_player.get_profile_id()).or_insert(0) += 1;
+      }
+    }
+    for game in &games {
+      let allies = game.get_my_team(profile_id);
+      if allies.len() == 0 {
+        continue;
+      }
+      let premade_allies = allies
+        .iter()
+        .filter(|ally_player| ally_games[&ally_player.get_profile_id()] > 1)
+        .count();
+      let queue_type = if premade_allies == allies.len() {
+        QueueType::Premade
+      } else if premade_allies > 0 {
+        QueueType::PartialPremade
+      } else {
+        QueueType::Solo
+      };
+      ret.insert(String::from(game.get_match_id()), queue_type);
+    }
+  }
+  return ret;
+}
+
+pub fn analyze(
+  match_history: &Vec<fetch::MatchHistoryGameResponse>,
+  tracker: &player_tracker::PlayerTracker,
+  sessions: &Vec<session::Session>,
+) -> PremadeStats {
+  let queue_types = classify_sessions(match_history, tracker.profile_id, sessions);
+  let mut stats = PremadeStats::default();
+  for game in match_history {
+    let queue_type = match queue_types.get(game.get_match_id()) {
+      Some(queue_type) => *queue_type,
+      None => continue,
+    };
+    let is_win = match tracker
+      .get_outcome(game.get_match_id())
+      .and_then(|outcome| outcome.is_win)
+    {
+      Some(is_win) => is_win,
+      None => continue,
+    };
+    match queue_type {
+      QueueType::Premade => {
+        stats.premade.add(is_win);
+        let allies = game.get_my_team(tracker.profile_id);
+        let mut profile_ids: Vec<i32> = allies.iter().map(|p| p.get_profile_id()).collect();
+        profile_ids.sort();
+        match stats
+          .groups
+          .iter_mut()
+          .find(|group| group.profile_ids == profile_ids)
+        {
+          Some(group) => group.record.add(is_win),
+          None => {
+            let mut record = QueueRecord::default();
+            record.add(is_win);
+            let mut names: Vec<String> = vec![];
+            for profile_id in &profile_ids {
+              if let Some(ally_player) = game.get_player_by_profile_id(*profile_id) {
+                names.push(ally_player.get_name());
+              }
+            }
+            stats.groups.push(PremadeGroup {
+              profile_ids,
+              names,
+              record,
+            });
+          }
+        }
+      }
+      QueueType::PartialPremade => stats.partial_premade.add(is_win),
+      QueueType::Solo => stats.solo.add(is_win),
+    }
+  }
+  stats
+    .groups
+    .sort_by(|a, b| b.record.games.cmp(&a.record.games));
+  return stats;
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct OpposingTeamPremade {
+  pub profile_ids: Vec<i32>,
+  pub names: Vec<String>,
+  pub queue_type: QueueType,
+  // the most games any two of these players have been on the same team in this match history
+  pub games_together: i32,
+}
+
+// the opponents' own histories are not fetched, so this only knows about the games they were in
+// with the tracked player
+pub fn detect_opposing_premades(
+  game: &fetch::MatchHistoryGameResponse,
+  match_history: &Vec<fetch::MatchHistoryGameResponse>,
+  profile_id: i32,
+) -> Vec<OpposingTeamPremade> {
+  let mut ret: Vec<OpposingTeamPremade> = vec![];
+  for team in game.get_opposing_teams(profile_id) {
+    if team.len() < 2 {
+      continue;
+    }
+    let mut linked: Vec<bool> = vec![false; team.len()];
+    let mut games_together = 0;
+    for i in 0..team.len() {
+      for j in (i + 1)..team.len() {
+        let a = team[i].get_profile_id();
+        let b = team[j].get_profile_id();
+        let count = match_history
+          .iter()
+          .filter(|other_game| other_game.get_match_id() != game.get_match_id())
+          .filter(|other_game| {
+            match (
+              other_game.get_team_id_by_profile_id(a),
+              other_game.get_team_id_by_profile_id(b),
+            ) {
+              (Some(team_a), Some(team_b)) => team_a == team_b,
+              _ => false,
+            }
+          })
+          .count() as i32;
+        if count >= OPPONENT_PREMADE_MIN_GAMES {
+          linked[i] = true;
+          linked[j] = true;
+        }
+        games_together = games_together.max(count);
+      }
+    }
+    let linked_count = linked.iter().filter(|l| **l).count();
+    if linked_count == 0 {
+      continue;
+    }
+    ret.push(OpposingTeamPremade {
+      profile_ids: team.iter().map(|p| p.get_profile_id()).collect(),
+      names: team.iter().map(|p| p.get_name()).collect(),
+      queue_type: if linked_count == team.len() {
+        QueueType::Premade
+      } else {
+        QueueType::PartialPremade
+      },
+      games_together,
+    });
+  }
+  return ret;
+}
@@ -0,0 +1,118 @@
+use crate::fetch;
+use serde::ser::{Serialize, Serializer, SerializeStruct};
+
+// a result is an upset when the side that won had less than this chance of winning
+const UPSET_PROBABILITY: f64 = 0.4;
+
+pub fn expected_score(rating: f64, other_rating: f64) -> f64 {
+  return 1.0 / (1.0 + 10f64.powf((other_rating - rating) / 400.0));
+}
+
+pub fn team_average_rating(team: &Vec<&fetch::MatchHistoryPlayerResponse>) -> Option<f64> {
+  let ratings: Vec<i32> = team.iter().filter_map(|player| player.rating).collect();
+  if ratings.len() == 0 {
+    return None;
+  }
+  let total: i32 = ratings.iter().sum();
+  Some(total as f64 / ratings.len() as f64)
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct MatchExpectation {
+  pub match_id: String,
+  pub started: i64,
+  pub my_team_rating: f64,
+  pub opposing_team_rating: f64,
+  pub expected_win: f64,
+  pub is_win: bool,
+  pub is_upset: bool,
+}
+
+impl MatchExpectation {
+  pub fn from_game(
+    game: &fetch::MatchHistoryGameResponse,
+    my_profile_id: i32,
+    is_win: bool,
+  ) -> Option<MatchExpectation> {
+    // elo expectations only hold for one side against another
+    if game.get_team_count() != 2 {
+      return None;
+    }
+    let mut my_team = game.get_my_team(my_profile_id);
+    my_team.push(game.get_player_by_profile_id(my_profile_id)?);
+    let my_team_rating = team_average_rating(&my_team)?;
+    let opposing_team_rating = team_average_rating(&game.get_opposing_team(my_profile_id))?;
+    let expected_win = expected_score(my_team_rating, opposing_team_rating);
+    let is_upset = if is_win {
+      expected_win < UPSET_PROBABILITY
+    } else {
+      1.0 - expected_win < UPSET_PROBABILITY
+    };
+    Some(MatchExpectation {
+      match_id: String::from(game.get_match_id()),
+      started: game.started,
+      my_team_rating,
+      opposing_team_rating,
+      expected_win,
+      is_win,
+      is_upset,
+    })
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Performance {
+  pub games: i32,
+  pub wins: i32,
+  pub expected_wins: f64,
+  pub upset_wins: i32,
+  pub upset_losses: i32,
+}
+
+impl Serialize for Performance {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    let mut state = serializer.serialize_struct("Performance", 6)?;
+    state.serialize_field("games", &self.games)?;
+    state.serialize_field("wins", &self.wins)?;
+    state.serialize_field("expected_wins", &self.expected_wins)?;
+    state.serialize_field("wins_above_expected", &self.wins_above_expected())?;
+    state.serialize_field("upset_wins", &self.upset_wins)?;
+    state.serialize_field("upset_losses", &self.upset_losses)?;
+    state.end()
+  }
+}
+
+impl Performance {
+  pub fn add(&mut self, expectation: &MatchExpectation) {
+    self.games += 1;
+    self.expected_wins += expectation.expected_win;
+    if expectation.is_win {
+      self.wins += 1;
+    }
+    if expectation.is_upset {
+      if expectation.is_win {
+        self.upset_wins += 1;
+      } else {
+        self.upset_losses += 1;
+      }
+    }
+  }
+  // positive when more games were won than the ratings predicted
+  pub fn wins_above_expected(&self) -> f64 {
+    return self.wins as f64 - self.expected_wins;
+  }
+  pub fn format(&self) -> String {
+    return format!(
+      "won {} of {} (expected {:.1}, {:+.1}), upset wins {}, upset losses {}",
+      self.wins,
+      self.games,
+      self.expected_wins,
+      self.wins_above_expected(),
+      self.upset_wins,
+      self.upset_losses
+    );
+  }
+}
@@ -0,0 +1,198 @@
+use crate::error::RuntimeError;
+use crate::fetch;
+use crate::player_tracker;
+
+pub const DEFAULT_PER_PAGE: usize = 50;
+pub const MAX_PER_PAGE: usize = 500;
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordSort {
+  Games,
+  WinRate,
+  LastPlayed,
+}
+
+impl RecordSort {
+  pub fn parse(sort: &str) -> Result<RecordSort, RuntimeError> {
+    match sort {
+      "games" => Ok(RecordSort::Games),
+      "win_rate" => Ok(RecordSort::WinRate),
+      "last_played" => Ok(RecordSort::LastPlayed),
+      _ => Err(RuntimeError::new(&format!(
+        "Unknown sort {}, expected games, win_rate or last_played",
+        sort
+      ))),
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordOptions {
+  pub sort: RecordSort,
+  pub ascending: bool,
+  pub min_games: i32,
+  // matched case-insensitively against every name the opponent has been seen with
+  pub name: Option<String>,
+  // pages start at 1, and no page means the first DEFAULT_PER_PAGE records
+  pub page: Option<usize>,
+  pub per_page: Option<usize>,
+  pub include_games: bool,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct RecordPage {
+  // all the records before filtering
+  pub total: usize,
+  // the records that matched the filters, across all pages
+  pub matching: usize,
+  pub page: usize,
+  pub per_page: usize,
+  pub pages: usize,
+  pub sort: RecordSort,
+  pub ascending: bool,
+  // the profile ids of the records on this page, in order, since records are keyed by profile id
+  pub profile_ids: Vec<i32>,
+  // only filled in when asked for, the games against each opponent on this page and the ratings
+  // in each of them
+  pub games: Option<std::collections::HashMap<i32, Vec<fetch::MatchHistoryGameResponse>>>,
+  pub ratings: Option<std::collections::HashMap<i32, Vec<player_tracker::EncounterRating>>>,
+}
+
+fn get_win_rate(record: &player_tracker::Record) -> f64 {
+  let games = record.wins_against + record.losses_to;
+  if games > 0 {
+    record.wins_against as f64 / games as f64
+  } else {
+    0.0
+  }
+}
+
+fn get_last_played(record: &player_tracker::Record) -> i64 {
+  record.games.last().map_or(0, |game| game.started)
+}
+
+fn matches_name(tracker: &player_tracker::PlayerTracker, profile_id: i32, name: &str) -> bool {
+  let name = name.to_lowercase();
+  let current_name = tracker
+    .players
+    .get(&profile_id)
+    .map(|player| player.get_name());
+  let seen_names = tracker
+    .names
+    .get(&profile_id)
+    .into_iter()
+    .flatten()
+    .map(|name_seen| name_seen.name.clone());
+  current_name
+    .into_iter()
+    .chain(seen_names)
+    .any(|seen| seen.to_lowercase().contains(&name))
+}
+
+fn compare_records(
+  a: &player_tracker::Record,
+  b: &player_tracker::Record,
+  sort: RecordSort,
+) -> std::cmp::Ordering {
+  match sort {
+    RecordSort::Games => a.games.len().cmp(&b.games.len()),
+    RecordSort::WinRate => get_win_rate(a)
+      .partial_cmp(&get_win_rate(b))
+      .unwrap_or(std::cmp::Ordering::Equal),
+    RecordSort::LastPlayed => get_last_played(a).cmp(&get_last_played(b)),
+  }
+}
+
+// the records that are not on the page are dropped from the tracker, along with the players and
+// names only they referred to, so the tracker should not be used for anything else afterwards
+pub fn select_records(
+  tracker: &mut player_tracker::PlayerTracker,
+  options: &RecordOptions,
+) -> RecordPage {
+  let total = tracker.records.len();
+  let mut records: Vec<&player_tracker::Record> = tracker
+    .records
+    .values()
+    .filter(|record| record.games.len() as i32 >= options.min_games)
+    .filter(|record| match &options.name {
+      Some(name) => matches_name(tracker, record.profile_id, name),
+      None => true,
+    })
+    .collect();
+  // ties are broken by profile id so that pages do not overlap
+  records.sort_by(|a, b| {
+    let ordering = compare_records(a, b, options.sort);
+    let ordering = if options.ascending {
+      ordering
+    } else {
+      ordering.reverse()
+    };
+    ordering.then(a.profile_id.cmp(&b.profile_id))
+  });
+  let matching = records.len();
+
+  let page = options.page.unwrap_or(1).max(1);
+  let per_page = options
+    .per_page
+    .unwrap_or(DEFAULT_PER_PAGE)
+    .max(1)
+    .min(MAX_PER_PAGE);
+  let profile_ids: Vec<i32> = records
+    .iter()
+    .skip((page - 1) * per_page)
+    .take(per_page)
+    .map(|record| record.profile_id)
+    .collect();
+  let page_records: Vec<&player_tracker::Record> = profile_ids
+    .iter()
+    .filter_map(|profile_id| tracker.records.get(profile_id))
+    .collect();
+  let (games, ratings) = if options.include_games {
+    (
+      Some(
+        page_records
+          .iter()
+          .map(|record| (record.profile_id, record.games.clone()))
+          .collect(),
+      ),
+      Some(
+        page_records
+          .iter()
+          .map(|record| (record.profile_id, record.ratings.clone()))
+          .collect(),
+      ),
+    )
+  } else {
+    (None, None)
+  };
+
+  let kept: std::collections::HashSet<i32> = profile_ids.iter().cloned().collect();
+  tracker
+    .records
+    .retain(|profile_id, _| kept.contains(profile_id));
+  let own_profile_id = tracker.profile_id;
+  let teammates: std::collections::HashSet<i32> = tracker.teammates.keys().cloned().collect();
+  let is_referred_to = |profile_id: &i32| {
+    *profile_id == own_profile_id || kept.contains(profile_id) || teammates.contains(profile_id)
+  };
+  tracker
+    .players
+    .retain(|profile_id, _| is_referred_to(profile_id));
+  tracker
+    .names
+    .retain(|profile_id, _| is_referred_to(profile_id));
+
+  RecordPage {
+    total,
+    matching,
+    page,
+    per_page,
+    pages: (matching + per_page - 1) / per_page,
+    sort: options.sort,
+    ascending: options.ascending,
+    profile_ids,
+    games,
+    ratings,
+  }
+}
@@ -0,0 +1,169 @@
+use crate::player_tracker;
+
+pub const DEFAULT_MIN_GAMES: i32 = 3;
+pub const DEFAULT_COUNT: usize = 5;
+
+// each half of the games against an opponent needs a couple of results to compare
+const MIN_GAMES_FOR_IMPROVEMENT: i32 = 4;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RivalOptions {
+  pub min_games: i32,
+  pub count: usize,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct Rival {
+  pub profile_id: i32,
+  pub name: String,
+  pub games: i32,
+  pub wins_against: i32,
+  pub losses_to: i32,
+  pub win_rate: f64,
+  // win rate in the later half of the games minus the win rate in the earlier half
+  pub improvement: Option<f64>,
+}
+
+impl Rival {
+  pub fn format(&self) -> String {
+    let improvement = match self.improvement {
+      Some(improvement) => format!(", {:+.0}% over time", improvement * 100.0),
+      None => String::default(),
+    };
+    return format!(
+      "{}: {} games, wins against {}, losses to {} ({:.0}%{})",
+      self.name,
+      self.games,
+      self.wins_against,
+      self.losses_to,
+      self.win_rate * 100.0,
+      improvement
+    );
+  }
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct Rivals {
+  pub most_played: Vec<Rival>,
+  pub nemeses: Vec<Rival>,
+  pub favorite_victims: Vec<Rival>,
+  pub most_improved: Vec<Rival>,
+}
+
+fn win_rate(results: &[bool]) -> f64 {
+  let wins = results.iter().filter(|is_win| **is_win).count();
+  return wins as f64 / results.len() as f64;
+}
+
+fn to_rival(tracker: &player_tracker::PlayerTracker, record: &player_tracker::Record) -> Rival {
+  let games = record.wins_against + record.losses_to;
+  let results = tracker.get_results_against(record.profile_id);
+  let improvement = if results.len() as i32 >= MIN_GAMES_FOR_IMPROVEMENT {
+    let (earlier, later) = results.split_at(results.len() / 2);
+    Some(win_rate(later) - win_rate(earlier))
+  } else {
+    None
+  };
+  Rival {
+    profile_id: record.profile_id,
+    name: match tracker.players.get(&record.profile_id) {
+      Some(player) => player.get_name(),
+      None => record.profile_id.to_string(),
+    },
+    games,
+    wins_against: record.wins_against,
+    losses_to: record.losses_to,
+    win_rate: if games > 0 {
+      record.wins_against as f64 / games as f64
+    } else {
+      0.0
+    },
+    improvement,
+  }
+}
+
+fn take(mut rivals: Vec<Rival>, count: usize) -> Vec<Rival> {
+  rivals.truncate(count);
+  return rivals;
+}
+
+pub fn rank_rivals(tracker: &player_tracker::PlayerTracker, options: &RivalOptions) -> Rivals {
+  let all: Vec<Rival> = tracker
+    .records
+    .values()
+    .map(|record| to_rival(tracker, record))
+    .filter(|rival| rival.games > 0)
+    .collect();
+
+  let mut most_played = all.clone();
+  most_played.sort_by(|a, b| b.games.cmp(&a.games).then(a.name.cmp(&b.name)));
+
+  let qualified: Vec<Rival> = all
+    .into_iter()
+    .filter(|rival| rival.games >= options.min_games)
+    .collect();
+
+  let mut nemeses: Vec<Rival> = qualified
+    .iter()
+    .filter(|rival| rival.win_rate < 0.5)
+    .cloned()
+    .collect();
+  nemeses.sort_by(|a, b| {
+    a.win_rate
+      .partial_cmp(&b.win_rate)
+      .unwrap_or(std::cmp::Ordering::Equal)
+      .then(b.losses_to.cmp(&a.losses_to))
+  });
+
+  let mut favorite_victims: Vec<Rival> = qualified
+    .iter()
+    .filter(|rival| rival.win_rate > 0.5)
+    .cloned()
+    .collect();
+  favorite_victims.sort_by(|a, b| {
+    b.win_rate
+      .partial_cmp(&a.win_rate)
+      .unwrap_or(std::cmp::Ordering::Equal)
+      .then(b.wins_against.cmp(&a.wins_against))
+  });
+
+  let mut most_improved: Vec<Rival> = qualified
+    .iter()
+    .filter(|rival| rival.improvement.unwrap_or(0.0) > 0.0)
+    .cloned()
+    .collect();
+  most_improved.sort_by(|a, b| {
+    b.improvement
+      .partial_cmp(&a.improvement)
+      .unwrap_or(std::cmp::Ordering::Equal)
+  });
+
+  Rivals {
+    most_played: take(most_played, options.count),
+    nemeses: take(nemeses, options.count),
+    favorite_victims: take(favorite_victims, options.count),
+    most_improved: take(most_improved, options.count),
+  }
+}
+
+impl Rivals {
+  pub fn format(&self) -> String {
+    let mut ret = String::default();
+    let sections = [
+      ("Most played", &self.most_played),
+      ("Nemeses", &self.nemeses),
+      ("Favorite victims", &self.favorite_victims),
+      ("Most improved", &self.most_improved),
+    ];
+    for (title, rivals) in sections.iter() {
+      if rivals.len() == 0 {
+        continue;
+      }
+      ret += &format!("{}:\n", title);
+      for rival in rivals.iter() {
+        ret += &format!("  {}\n", rival.format());
+      }
+    }
+    return ret;
+  }
+}
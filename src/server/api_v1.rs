@@ -0,0 +1,397 @@
+// the response types of /api/v1. they are kept apart from the internal types so that changes to
+// the tracker do not change the wire format; a field can be added here, but never renamed or
+// removed without a new version. the openapi document below has to be updated along with them
+use crate::fetch;
+use crate::player_tracker;
+use crate::prediction;
+use crate::rating;
+use crate::server::endpoints::LookupPlayerResponse;
+
+pub const API_VERSION: &str = "1.0.0";
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct PerformanceV1 {
+  pub games: i32,
+  pub wins: i32,
+  pub expected_wins: f64,
+  pub wins_above_expected: f64,
+}
+
+impl PerformanceV1 {
+  fn new(performance: &rating::Performance) -> PerformanceV1 {
+    PerformanceV1 {
+      games: performance.games,
+      wins: performance.wins,
+      expected_wins: performance.expected_wins,
+      wins_above_expected: performance.wins_above_expected(),
+    }
+  }
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct OpponentV1 {
+  pub profile_id: i32,
+  pub name: String,
+  pub aliases: Vec<String>,
+  pub games: i32,
+  pub wins_against: i32,
+  pub losses_to: i32,
+  pub last_played: i64,
+  pub average_rating: Option<f64>,
+  pub min_rating: Option<i32>,
+  pub max_rating: Option<i32>,
+  pub average_rating_difference: Option<f64>,
+  pub performance: PerformanceV1,
+}
+
+impl OpponentV1 {
+  fn new(record: &player_tracker::Record, tracker: &player_tracker::PlayerTracker) -> OpponentV1 {
+    OpponentV1 {
+      profile_id: record.profile_id,
+      name: get_name(tracker, record.profile_id),
+      aliases: get_aliases(tracker, record.profile_id),
+      games: record.games.len() as i32,
+      wins_against: record.wins_against,
+      losses_to: record.losses_to,
+      last_played: record.games.last().map_or(0, |game| game.started),
+      average_rating: record.get_average_opponent_rating(),
+      min_rating: record.get_min_opponent_rating(),
+      max_rating: record.get_max_opponent_rating(),
+      average_rating_difference: record.get_average_rating_difference(),
+      performance: PerformanceV1::new(&record.performance),
+    }
+  }
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct TeammateV1 {
+  pub profile_id: i32,
+  pub name: String,
+  pub aliases: Vec<String>,
+  pub wins_with: i32,
+  pub losses_with: i32,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct GamePlayerV1 {
+  pub profile_id: i32,
+  pub name: String,
+  pub team: Option<i32>,
+  pub rating: Option<i32>,
+  pub won: Option<bool>,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct GameV1 {
+  pub match_id: String,
+  pub started: i64,
+  pub finished: Option<i64>,
+  pub ranked: bool,
+  pub format: String,
+  pub map_type: Option<i32>,
+  pub players: Vec<GamePlayerV1>,
+}
+
+impl GameV1 {
+  fn new(game: &fetch::MatchHistoryGameResponse) -> GameV1 {
+    GameV1 {
+      match_id: String::from(game.get_match_id()),
+      started: game.started,
+      finished: game.finished.map(|finished| finished as i64),
+      ranked: game.ranked,
+      format: game.get_format(),
+      map_type: game.map_type,
+      players: game
+        .players
+        .iter()
+        .map(|player| GamePlayerV1 {
+          profile_id: player.get_profile_id(),
+          name: player.get_name(),
+          team: player.get_team_id(),
+          rating: player.rating,
+          won: player.won,
+        })
+        .collect(),
+    }
+  }
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct PredictionFactorV1 {
+  pub kind: String,
+  pub profile_id: Option<i32>,
+  pub log_odds: f64,
+  pub description: String,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct PredictionV1 {
+  pub win_probability: f64,
+  pub factors: Vec<PredictionFactorV1>,
+}
+
+impl PredictionV1 {
+  fn new(prediction: &prediction::Prediction) -> PredictionV1 {
+    PredictionV1 {
+      win_probability: prediction.win_probability,
+      factors: prediction
+        .factors
+        .iter()
+        .map(|factor| PredictionFactorV1 {
+          kind: factor.kind.clone(),
+          profile_id: factor.profile_id,
+          log_odds: factor.log_odds,
+          description: factor.description.clone(),
+        })
+        .collect(),
+    }
+  }
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct PlayerLookupV1 {
+  pub api_version: &'static str,
+  pub profile_id: i32,
+  pub name: String,
+  pub leaderboard: String,
+  pub wins: i32,
+  pub losses: i32,
+  pub ffa_wins: i32,
+  pub ffa_losses: i32,
+  pub performance: PerformanceV1,
+  pub opponents: Vec<OpponentV1>,
+  pub teammates: Vec<TeammateV1>,
+  pub most_recent_game: GameV1,
+  pub prediction: Option<PredictionV1>,
+}
+
+fn get_name(tracker: &player_tracker::PlayerTracker, profile_id: i32) -> String {
+  match tracker.players.get(&profile_id) {
+    Some(player) => player.get_name(),
+    None => profile_id.to_string(),
+  }
+}
+
+fn get_aliases(tracker: &player_tracker::PlayerTracker, profile_id: i32) -> Vec<String> {
+  tracker
+    .get_aliases(profile_id)
+    .iter()
+    .map(|name_seen| name_seen.name.clone())
+    .collect()
+}
+
+impl PlayerLookupV1 {
+  pub fn new(response: &LookupPlayerResponse) -> PlayerLookupV1 {
+    let tracker = &response.tracker;
+    // most played first, so the order does not depend on the hash map
+    let mut opponents: Vec<OpponentV1> = tracker
+      .records
+      .values()
+      .map(|record| OpponentV1::new(record, tracker))
+      .collect();
+    opponents.sort_by(|a, b| b.games.cmp(&a.games).then(a.profile_id.cmp(&b.profile_id)));
+    let mut teammates: Vec<TeammateV1> = tracker
+      .teammates
+      .values()
+      .map(|teammate| TeammateV1 {
+        profile_id: teammate.profile_id,
+        name: get_name(tracker, teammate.profile_id),
+        aliases: get_aliases(tracker, teammate.profile_id),
+        wins_with: teammate.wins_with,
+        losses_with: teammate.losses_with,
+      })
+      .collect();
+    teammates.sort_by(|a, b| {
+      (b.wins_with + b.losses_with)
+        .cmp(&(a.wins_with + a.losses_with))
+        .then(a.profile_id.cmp(&b.profile_id))
+    });
+    PlayerLookupV1 {
+      api_version: API_VERSION,
+      profile_id: response.profile_id,
+      name: response.player_name.clone(),
+      leaderboard: response.leaderboard_name.clone(),
+      wins: tracker.wins,
+      losses: tracker.losses,
+      ffa_wins: tracker.ffa_wins,
+      ffa_losses: tracker.ffa_losses,
+      performance: PerformanceV1::new(&tracker.performance),
+      opponents,
+      teammates,
+      most_recent_game: GameV1::new(&response.most_recent_game),
+      prediction: response.prediction.as_ref().map(PredictionV1::new),
+    }
+  }
+}
+
+fn nullable(mut schema: serde_json::Value) -> serde_json::Value {
+  schema["nullable"] = serde_json::json!(true);
+  schema
+}
+
+fn reference(name: &str) -> serde_json::Value {
+  serde_json::json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+pub fn openapi_document() -> serde_json::Value {
+  let integer = serde_json::json!({ "type": "integer" });
+  let number = serde_json::json!({ "type": "number" });
+  let string = serde_json::json!({ "type": "string" });
+  let boolean = serde_json::json!({ "type": "boolean" });
+  let array = |items: serde_json::Value| serde_json::json!({ "type": "array", "items": items });
+  let error = serde_json::json!({
+    "description": "The player could not be looked up",
+    "content": { "application/json": { "schema": string } }
+  });
+  let query_parameter = |name: &str, schema: &serde_json::Value, description: &str| {
+    serde_json::json!({
+      "name": name,
+      "in": "query",
+      "required": false,
+      "schema": schema,
+      "description": description
+    })
+  };
+  serde_json::json!({
+    "openapi": "3.0.3",
+    "info": {
+      "title": "aoe2de_head2head",
+      "version": API_VERSION,
+      "description": "Age of Empires 2: DE win and loss records against other players, from the aoe2.net api"
+    },
+    "paths": {
+      "/api/v1/players/{player_name}/{leaderboard_name}": {
+        "get": {
+          "summary": "Look up a player's records against their opponents and teammates",
+          "parameters": [
+            { "name": "player_name", "in": "path", "required": true, "schema": string },
+            {
+              "name": "leaderboard_name",
+              "in": "path",
+              "required": true,
+              "schema": { "type": "string", "enum": ["solo", "team"] }
+            },
+            query_parameter("since", &string, "Only games started on or after this date, yyyy-mm-dd"),
+            query_parameter("until", &string, "Only games started on or before this date, yyyy-mm-dd"),
+            query_parameter("last", &integer, "Only the last n games"),
+            query_parameter("version", &string, "Only games on this game version"),
+            query_parameter("min_version", &integer, "Only games on this game version or later"),
+            query_parameter("ranked", &boolean, "Only ranked games"),
+            query_parameter("team_size", &integer, "Only games with two teams of this size"),
+            query_parameter("map", &integer, "Only games on this map type")
+          ],
+          "responses": {
+            "200": {
+              "description": "The player's records",
+              "content": { "application/json": { "schema": reference("PlayerLookup") } }
+            },
+            "404": error,
+            "429": { "description": "Too many requests, try again after the Retry-After header's seconds" }
+          }
+        }
+      },
+      "/api/v1/openapi.json": {
+        "get": {
+          "summary": "This document",
+          "responses": { "200": { "description": "The OpenAPI document" } }
+        }
+      }
+    },
+    "components": {
+      "schemas": {
+        "Performance": {
+          "type": "object",
+          "properties": {
+            "games": integer,
+            "wins": integer,
+            "expected_wins": number,
+            "wins_above_expected": number
+          }
+        },
+        "Opponent": {
+          "type": "object",
+          "properties": {
+            "profile_id": integer,
+            "name": string,
+            "aliases": array(string.clone()),
+            "games": integer,
+            "wins_against": integer,
+            "losses_to": integer,
+            "last_played": integer,
+            "average_rating": nullable(number.clone()),
+            "min_rating": nullable(integer.clone()),
+            "max_rating": nullable(integer.clone()),
+            "average_rating_difference": nullable(number.clone()),
+            "performance": reference("Performance")
+          }
+        },
+        "Teammate": {
+          "type": "object",
+          "properties": {
+            "profile_id": integer,
+            "name": string,
+            "aliases": array(string.clone()),
+            "wins_with": integer,
+            "losses_with": integer
+          }
+        },
+        "GamePlayer": {
+          "type": "object",
+          "properties": {
+            "profile_id": integer,
+            "name": string,
+            "team": nullable(integer.clone()),
+            "rating": nullable(integer.clone()),
+            "won": nullable(boolean.clone())
+          }
+        },
+        "Game": {
+          "type": "object",
+          "properties": {
+            "match_id": string,
+            "started": integer,
+            "finished": nullable(integer.clone()),
+            "ranked": boolean,
+            "format": string,
+            "map_type": nullable(integer.clone()),
+            "players": array(reference("GamePlayer"))
+          }
+        },
+        "PredictionFactor": {
+          "type": "object",
+          "properties": {
+            "kind": { "type": "string", "enum": ["rating", "head_to_head", "teammate"] },
+            "profile_id": nullable(integer.clone()),
+            "log_odds": number,
+            "description": string
+          }
+        },
+        "Prediction": {
+          "type": "object",
+          "properties": {
+            "win_probability": number,
+            "factors": array(reference("PredictionFactor"))
+          }
+        },
+        "PlayerLookup": {
+          "type": "object",
+          "properties": {
+            "api_version": string,
+            "profile_id": integer,
+            "name": string,
+            "leaderboard": string,
+            "wins": integer,
+            "losses": integer,
+            "ffa_wins": integer,
+            "ffa_losses": integer,
+            "performance": reference("Performance"),
+            "opponents": array(reference("Opponent")),
+            "teammates": array(reference("Teammate")),
+            "most_recent_game": reference("Game"),
+            "prediction": { "allOf": [reference("Prediction")], "nullable": true }
+          }
+        }
+      }
+    }
+  })
+}
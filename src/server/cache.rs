@@ -5,6 +5,10 @@ use rusqlite::named_params;
 use rusqlite::ToSql;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::aliases;
+use crate::fetch;
+use crate::player_tracker;
+
 const DB_NAME: &str = "request-cache.db";
 
 // 900s = 15 minutes
@@ -27,6 +31,34 @@ pub fn init_cache() -> rusqlite::Result<()> {
   )",
     NO_PARAMS,
   )?;
+  conn.execute(
+    "CREATE Table If not exists player_names (
+      profile_id integer not null,
+      name text not null,
+      first_seen integer not null,
+      last_seen integer not null
+  )",
+    NO_PARAMS,
+  )?;
+  // databases from before the unique index could hold the same name twice
+  conn.execute(
+    "DELETE FROM player_names WHERE rowid NOT IN (
+      SELECT min(rowid) FROM player_names GROUP BY profile_id, name
+  )",
+    NO_PARAMS,
+  )?;
+  conn.execute(
+    "CREATE UNIQUE INDEX IF NOT EXISTS player_names_profile_name ON player_names(profile_id, name)",
+    NO_PARAMS,
+  )?;
+  conn.execute(
+    "CREATE Table If not exists matches (
+      match_id text primary key not null,
+      value text not null,
+      last_updated text not null
+  )",
+    NO_PARAMS,
+  )?;
   Ok(())
 }
 
@@ -105,3 +137,125 @@ pub fn select_from_cache(key: &str) -> String {
 pub fn insert_into_cache(key: &str, value: &str) {
   set_value(key, value);
 }
+
+pub fn save_player_names(
+  names: &std::collections::HashMap<i32, Vec<aliases::NameSeen>>,
+) -> rusqlite::Result<()> {
+  let mut conn = Connection::open(DB_NAME)?;
+  let tx = conn.transaction()?;
+  {
+    let mut stmt = tx.prepare(
+      "INSERT INTO player_names (profile_id, name, first_seen, last_seen) VALUES(?1, ?2, ?3, ?4)
+      ON CONFLICT(profile_id, name) DO UPDATE SET
+        first_seen = min(first_seen, excluded.first_seen),
+        last_seen = max(last_seen, excluded.last_seen)",
+    )?;
+    for (profile_id, profile_names) in names {
+      for name_seen in profile_names {
+        stmt.execute(params![
+          profile_id,
+          name_seen.name,
+          name_seen.first_seen,
+          name_seen.last_seen
+        ])?;
+      }
+    }
+  }
+  tx.commit()
+}
+
+// the profile ids are passed as one json array, a lookup can have more of them than sqlite allows
+// parameters in a query
+pub fn select_player_names(
+  profile_ids: &Vec<i32>,
+) -> rusqlite::Result<std::collections::HashMap<i32, Vec<aliases::NameSeen>>> {
+  let conn = Connection::open(DB_NAME)?;
+  let mut stmt = conn.prepare(
+    "SELECT profile_id, name, first_seen, last_seen FROM player_names
+    WHERE profile_id IN (SELECT value FROM json_each(?1))
+    ORDER BY profile_id, last_seen DESC",
+  )?;
+  let profile_ids_json = serde_json::to_string(profile_ids).unwrap_or(String::from("[]"));
+  let rows = stmt.query_map(params![profile_ids_json], |row| {
+    Ok((
+      row.get::<_, i32>(0)?,
+      aliases::NameSeen {
+        name: row.get(1)?,
+        first_seen: row.get(2)?,
+        last_seen: row.get(3)?,
+      },
+    ))
+  })?;
+  let mut ret: std::collections::HashMap<i32, Vec<aliases::NameSeen>> = profile_ids
+    .iter()
+    .map(|profile_id| (*profile_id, vec![]))
+    .collect();
+  for row in rows {
+    let (profile_id, name_seen) = row?;
+    ret.entry(profile_id).or_default().push(name_seen);
+  }
+  Ok(ret)
+}
+
+pub fn select_all_player_names(
+) -> rusqlite::Result<std::collections::HashMap<i32, Vec<aliases::NameSeen>>> {
+  let conn = Connection::open(DB_NAME)?;
+  let mut stmt =
+    conn.prepare("SELECT profile_id, name, first_seen, last_seen FROM player_names")?;
+  let rows = stmt.query_map(NO_PARAMS, |row| {
+    Ok((
+      row.get::<_, i32>(0)?,
+      aliases::NameSeen {
+        name: row.get(1)?,
+        first_seen: row.get(2)?,
+        last_seen: row.get(3)?,
+      },
+    ))
+  })?;
+  let mut ret: std::collections::HashMap<i32, Vec<aliases::NameSeen>> =
+    std::collections::HashMap::new();
+  for row in rows {
+    let (profile_id, name_seen) = row?;
+    ret.entry(profile_id).or_default().push(name_seen);
+  }
+  Ok(ret)
+}
+
+// stores the names the tracker has seen and merges in the ones seen in earlier lookups
+pub fn update_player_names(tracker: &mut player_tracker::PlayerTracker) -> rusqlite::Result<()> {
+  save_player_names(&tracker.names)?;
+  let profile_ids: Vec<i32> = tracker.names.keys().cloned().collect();
+  let known_names = select_player_names(&profile_ids)?;
+  tracker.merge_names(&known_names);
+  Ok(())
+}
+
+// every game of a fetched match history is kept by match id, so single matches can be looked up
+// without going to aoe2.net. unlike the request cache these do not expire; a game is replaced
+// whenever it is seen again, which fills in the result of games that were still in progress
+pub fn save_matches(match_history: &Vec<fetch::MatchHistoryGameResponse>) -> rusqlite::Result<()> {
+  let mut conn = Connection::open(DB_NAME)?;
+  let tx = conn.transaction()?;
+  for game in match_history {
+    let value = serde_json::to_string(game).unwrap_or(String::from(""));
+    tx.execute(
+      "INSERT OR REPLACE INTO matches (match_id, value, last_updated)
+      VALUES(?1, ?2, strftime('%s','now'))",
+      params![game.get_match_id(), value],
+    )?;
+  }
+  tx.commit()
+}
+
+pub fn select_match(match_id: &str) -> rusqlite::Result<Option<fetch::MatchHistoryGameResponse>> {
+  let conn = Connection::open(DB_NAME)?;
+  let mut stmt = conn.prepare("SELECT value FROM matches WHERE match_id = ?1")?;
+  let mut rows = stmt.query(params![match_id])?;
+  match rows.next()? {
+    Some(row) => {
+      let value: String = row.get(0)?;
+      Ok(serde_json::from_str(&value).ok())
+    }
+    None => Ok(None),
+  }
+}
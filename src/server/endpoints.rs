@@ -1,23 +1,136 @@
-use actix_web::{get, Responder, HttpResponse, HttpRequest, web};
+use actix_web::{get, post, Responder, HttpResponse, HttpRequest, web};
+use futures::StreamExt;
 use actix_files::NamedFile;
 
+use crate::balance;
+use crate::batch;
+use crate::clan;
+use crate::common_opponents;
 use crate::error::RuntimeError;
+use crate::match_detail;
 use crate::player_tracker;
+use crate::prediction;
+use crate::premade;
+use crate::record_page;
+use crate::rivals;
 
 // taken from https://github.com/diegopacheco/rust-playground/tree/master/rust-microservice/news-service/src
 
 use crate::fetch;
 use crate::format;
+use crate::match_filter;
+use crate::server::api_v1;
 use crate::server::cache;
+use crate::server::live;
+use crate::server::overlay;
+use crate::server::rate_limit;
+use crate::session;
+use crate::time_of_day;
+use crate::tracking;
 
 #[derive(serde::Serialize)]
-struct LookupPlayerResponse {
-  profile_id: i32,
-  player_name: String,
-  leaderboard_id: i32,
-  leaderboard_name: String,
-  tracker: player_tracker::PlayerTracker,
-  most_recent_game: fetch::MatchHistoryGameResponse,
+pub struct LookupPlayerResponse {
+  pub profile_id: i32,
+  pub player_name: String,
+  pub leaderboard_id: i32,
+  pub leaderboard_name: String,
+  pub tracker: player_tracker::PlayerTracker,
+  pub most_recent_game: fetch::MatchHistoryGameResponse,
+  pub prediction: Option<prediction::Prediction>,
+  pub time_of_day: time_of_day::TimeOfDayStats,
+  pub last_session: Option<session::Session>,
+  pub premade: premade::PremadeStats,
+  pub opposing_premades: Vec<premade::OpposingTeamPremade>,
+  pub clans: Vec<clan::ClanRecord>,
+  // only on /lookup, where the records in the tracker are cut down to this page
+  pub records_page: Option<record_page::RecordPage>,
+}
+
+const DEFAULT_SESSION_COUNT: usize = 10;
+
+#[derive(serde::Deserialize)]
+pub struct LookupQuery {
+  since: Option<String>,
+  until: Option<String>,
+  last: Option<usize>,
+  version: Option<String>,
+  min_version: Option<i64>,
+  ranked: Option<bool>,
+  team_size: Option<i32>,
+  map: Option<i32>,
+  timezone: Option<String>,
+  utc_offset: Option<String>,
+  session_gap: Option<i64>,
+  // rivals and sessions only, each with its own default
+  count: Option<usize>,
+  min_games: Option<i32>,
+  clan_patterns: Option<String>,
+  // paging of the records in the lookup response
+  records_min_games: Option<i32>,
+  sort: Option<String>,
+  order: Option<String>,
+  name: Option<String>,
+  page: Option<usize>,
+  per_page: Option<usize>,
+  include_games: Option<bool>,
+}
+
+impl LookupQuery {
+  fn to_match_filter(&self) -> Result<match_filter::MatchFilter, RuntimeError> {
+    Ok(match_filter::MatchFilter {
+      since: match &self.since {
+        Some(date) => Some(match_filter::parse_since(date)?),
+        None => None,
+      },
+      until: match &self.until {
+        Some(date) => Some(match_filter::parse_until(date)?),
+        None => None,
+      },
+      last: self.last,
+      version: self.version.clone(),
+      min_version: self.min_version,
+      ranked_only: self.ranked.unwrap_or(false),
+      team_size: self.team_size,
+      map_type: self.map,
+    })
+  }
+  // session_gap is given in minutes
+  fn get_session_gap_secs(&self) -> i64 {
+    match self.session_gap {
+      Some(minutes) => minutes * 60,
+      None => session::DEFAULT_SESSION_GAP_SECS,
+    }
+  }
+  fn get_clan_patterns(&self) -> Result<Vec<clan::ClanPattern>, RuntimeError> {
+    match &self.clan_patterns {
+      Some(patterns) => clan::parse_patterns(patterns),
+      None => clan::parse_patterns(clan::DEFAULT_PATTERNS),
+    }
+  }
+  fn to_record_options(&self) -> Result<record_page::RecordOptions, RuntimeError> {
+    let ascending = match self.order.as_deref() {
+      Some("asc") => true,
+      Some("desc") | None => false,
+      Some(order) => {
+        return Err(RuntimeError::new(&format!(
+          "Unknown order {}, expected asc or desc",
+          order
+        )))
+      }
+    };
+    Ok(record_page::RecordOptions {
+      sort: match &self.sort {
+        Some(sort) => record_page::RecordSort::parse(sort)?,
+        None => record_page::RecordSort::Games,
+      },
+      ascending,
+      min_games: self.records_min_games.unwrap_or(0),
+      name: self.name.clone(),
+      page: self.page,
+      per_page: self.per_page,
+      include_games: self.include_games.unwrap_or(false),
+    })
+  }
 }
 
 fn log_request(req: &HttpRequest) {
@@ -63,6 +176,7 @@ async fn lookup_match_history_with_cache(
     };
     let result_string = serde_json::to_string(&match_history).unwrap_or(String::from(""));
     cache::insert_into_cache(&cache_key, &result_string);
+    save_matches(&match_history);
   } else {
     println!("[SRV] Cache hit match history, using cache");
     match_history = match serde_json::from_str(&cache_result) {
@@ -76,33 +190,215 @@ async fn lookup_match_history_with_cache(
   Ok(match_history)
 }
 
+// fetches the match history even when the cached one is still fresh, e.g. once a game finishes
+pub async fn refresh_match_history(profile_id: i32) -> Result<(), RuntimeError> {
+  let match_history = match fetch::fetch_match_history_async(profile_id).await? {
+    Some(m) => m,
+    None => return Err(RuntimeError::new("Could not get match history.")),
+  };
+  let result_string = serde_json::to_string(&match_history).unwrap_or(String::from(""));
+  cache::insert_into_cache(&format!("matches-{}", profile_id), &result_string);
+  save_matches(&match_history);
+  Ok(())
+}
+
+fn save_matches(match_history: &Vec<fetch::MatchHistoryGameResponse>) {
+  if let Err(e) = cache::save_matches(match_history) {
+    println!("[DB] Could not save matches: {:?}", e);
+  }
+}
+
+// the names of civs and maps only make the match details easier to read, so a lookup does not
+// fail without them
+async fn lookup_strings_with_cache() -> Option<fetch::StringsResponse> {
+  let cache_key = "strings";
+  let cache_result = cache::select_from_cache(cache_key);
+  if cache_result != "" {
+    println!("[SRV] Cache hit strings, using cache");
+    if let Ok(strings) = serde_json::from_str(&cache_result) {
+      return Some(strings);
+    }
+  }
+  println!("[SRV] Cache miss strings, fetching result");
+  match fetch::fetch_strings_async().await {
+    Ok(Some(strings)) => {
+      let result_string = serde_json::to_string(&strings).unwrap_or(String::from(""));
+      cache::insert_into_cache(cache_key, &result_string);
+      Some(strings)
+    }
+    Ok(None) => None,
+    Err(e) => {
+      println!("[SRV] Could not get strings: {:?}", e);
+      None
+    }
+  }
+}
+
+// finished games never change, so they are served from the cache database when they are in it.
+// games that were still in progress when they were saved are fetched again
+pub async fn create_match_response(
+  match_id: &str,
+) -> Result<match_detail::MatchDetail, RuntimeError> {
+  if match_id == "" || !match_id.chars().all(|c| c.is_ascii_digit()) {
+    return Err(RuntimeError::new(&format!("Invalid match id {}", match_id)));
+  }
+  let cached = match cache::select_match(match_id) {
+    Ok(cached) => cached,
+    Err(e) => {
+      println!("[DB] Could not get match: {:?}", e);
+      None
+    }
+  };
+  let (game, source) = match cached {
+    Some(game) if game.finished.is_some() => {
+      println!("[SRV] Cache hit match, using cache");
+      (game, match_detail::MatchSource::Cache)
+    }
+    cached => {
+      println!("[SRV] Cache miss match, fetching result");
+      // a game that was in progress when it was cached is still better than no game
+      match (fetch::fetch_match_async(match_id).await, cached) {
+        (Ok(Some(game)), _) => {
+          save_matches(&vec![game.clone()]);
+          (game, match_detail::MatchSource::Aoe2net)
+        }
+        (Ok(None), Some(game)) => (game, match_detail::MatchSource::Cache),
+        (Err(e), Some(game)) => {
+          println!("[SRV] Could not refresh match, using cache: {:?}", e);
+          (game, match_detail::MatchSource::Cache)
+        }
+        (Ok(None), None) => return Err(RuntimeError::new("Could not get match.")),
+        (Err(e), None) => return Err(e),
+      }
+    }
+  };
+  let strings = lookup_strings_with_cache().await;
+  Ok(match_detail::MatchDetail::new(
+    &game,
+    strings.as_ref(),
+    source,
+  ))
+}
+
+async fn lookup_rating_history_with_cache(
+  profile_id: i32,
+  leaderboard_id: fetch::LeaderboardId,
+) -> Result<Vec<fetch::RatingHistoryResponse>, RuntimeError> {
+  let cache_key = format!("ratinghistory-{}-{}", profile_id, leaderboard_id);
+  let cache_result = cache::select_from_cache(&cache_key);
+  let rating_history: Vec<fetch::RatingHistoryResponse>;
+  if cache_result == "" {
+    println!("[SRV] Cache miss rating history, fetching result");
+    rating_history = match fetch::fetch_rating_history_async(profile_id, leaderboard_id).await? {
+      Some(m) => m,
+      None => return Err(RuntimeError::new("Could not get rating history.")),
+    };
+    let result_string = serde_json::to_string(&rating_history).unwrap_or(String::from(""));
+    cache::insert_into_cache(&cache_key, &result_string);
+  } else {
+    println!("[SRV] Cache hit rating history, using cache");
+    rating_history = match serde_json::from_str(&cache_result) {
+      Ok(p) => p,
+      Err(e) => panic!(
+        "Error parsing json from cache (rating_history_resp): {:?}",
+        e
+      ),
+    };
+  }
+  Ok(rating_history)
+}
+
+struct TrackedPlayer {
+  player_resp: fetch::PlayerResponse,
+  match_history: Vec<fetch::MatchHistoryGameResponse>,
+  tracker: player_tracker::PlayerTracker,
+  // none when the filter left out the newest game, so the rating after the last session is unknown
+  current_rating: Option<i32>,
+}
+
+async fn track_player(
+  player_name: &str,
+  leaderboard_id: fetch::LeaderboardId,
+  filter: &match_filter::MatchFilter,
+) -> Result<TrackedPlayer, RuntimeError> {
+  let player_resp = lookup_player_with_cache(player_name, leaderboard_id).await?;
+  let tracked = track_history(player_resp.profile_id, leaderboard_id, filter).await?;
+  let current_rating = if tracked.includes_newest_game {
+    player_resp.rating
+  } else {
+    None
+  };
+
+  Ok(TrackedPlayer {
+    player_resp,
+    match_history: tracked.match_history,
+    tracker: tracked.tracker,
+    current_rating,
+  })
+}
+
+pub async fn track_profile(
+  profile_id: i32,
+  leaderboard_id: fetch::LeaderboardId,
+  filter: &match_filter::MatchFilter,
+) -> Result<
+  (
+    Vec<fetch::MatchHistoryGameResponse>,
+    player_tracker::PlayerTracker,
+  ),
+  RuntimeError,
+> {
+  let tracked = track_history(profile_id, leaderboard_id, filter).await?;
+  Ok((tracked.match_history, tracked.tracker))
+}
+
+async fn track_history(
+  profile_id: i32,
+  leaderboard_id: fetch::LeaderboardId,
+  filter: &match_filter::MatchFilter,
+) -> Result<tracking::TrackedHistory, RuntimeError> {
+  tracking::track_history(
+    profile_id,
+    leaderboard_id,
+    filter,
+    lookup_match_history_with_cache,
+    lookup_rating_history_with_cache,
+  )
+  .await
+}
+
 async fn create_lookup_player_response(
   player_name: &str,
   leaderboard_id: fetch::LeaderboardId,
+  query: &LookupQuery,
 ) -> Result<LookupPlayerResponse, RuntimeError> {
-  let player_resp = lookup_player_with_cache(player_name, leaderboard_id).await?;
+  let filter = query.to_match_filter()?;
+  let clan_patterns = query.get_clan_patterns()?;
+  let timezone = match query.timezone.as_ref().or(query.utc_offset.as_ref()) {
+    Some(timezone) => time_of_day::parse_timezone(timezone)?,
+    None => time_of_day::Timezone::utc(),
+  };
+  let TrackedPlayer {
+    player_resp,
+    match_history,
+    tracker: pt,
+    current_rating,
+  } = track_player(player_name, leaderboard_id, &filter).await?;
   let player_name = player_resp.name.clone();
   let profile_id = player_resp.profile_id;
   let leaderboard_name = format::leaderboard_id_to_name(leaderboard_id);
-  let mut pt = player_tracker::PlayerTracker::new(profile_id);
-
-  let mut match_history = lookup_match_history_with_cache(profile_id).await?;
-  match_history.retain(|mh| mh.get_leaderboard_id() == leaderboard_id);
-  if match_history.len() == 0 {
-    return Err(RuntimeError::new("Player has not played any games."));
-  }
-
-  pt.track_players(&match_history);
 
   let last_match_resp = match fetch::fetch_latest_match_async(profile_id).await? {
     Some(m) => m,
     None => return Err(RuntimeError::new("Could not get last match")),
   };
 
-  let last_match = &mut last_match_resp.last_match.clone().unwrap();
+  // a player without any games has no last match, the match history is used instead
+  let last_match = last_match_resp.last_match.clone();
 
-  let last_match_leaderboard_id =
-    fetch::LeaderboardId::to_leaderboard_id(last_match.leaderboard_id.unwrap_or(3));
+  let last_match_leaderboard_id = last_match.as_ref().map(|last_match| {
+    fetch::LeaderboardId::to_leaderboard_id(last_match.leaderboard_id.unwrap_or(3))
+  });
 
   // let player_in_last_match = match last_match.get_player_by_profile_id_mut(profile_id) {
   //   Some(p) => p,
@@ -117,20 +413,236 @@ async fn create_lookup_player_response(
   //   player_in_last_match.won = Some(false);
   // }
 
+  let most_recent_game = match last_match {
+    Some(last_match) if last_match_leaderboard_id == Some(leaderboard_id) => last_match,
+    _ => match_history[0].clone(),
+  };
+  let prediction = match most_recent_game.finished {
+    Some(_) => None,
+    None => Some(prediction::predict_game(&most_recent_game, &pt)),
+  };
+  let time_of_day = time_of_day::TimeOfDayStats::from_outcomes(&pt.outcomes, &timezone);
+  let sessions = session::detect_sessions(
+    &match_history,
+    &pt,
+    query.get_session_gap_secs(),
+    current_rating,
+  );
+  let premade = premade::analyze(&match_history, &pt, &sessions);
+  let opposing_premades =
+    premade::detect_opposing_premades(&most_recent_game, &match_history, profile_id);
+  let last_session = sessions.into_iter().next();
+  let clans = clan::analyze(&match_history, &pt, &clan_patterns);
+
   Ok(LookupPlayerResponse {
     profile_id,
     player_name,
     leaderboard_id: leaderboard_id as i32,
     leaderboard_name,
     tracker: pt,
-    most_recent_game: if last_match_leaderboard_id == leaderboard_id {
-      last_match.clone()
-    } else {
-      match_history[0].clone()
-    },
+    most_recent_game,
+    prediction,
+    time_of_day,
+    last_session,
+    premade,
+    opposing_premades,
+    clans,
+    records_page: None,
+  })
+}
+
+async fn create_lookup_player_page_response(
+  player_name: &str,
+  leaderboard_id: fetch::LeaderboardId,
+  query: &LookupQuery,
+) -> Result<LookupPlayerResponse, RuntimeError> {
+  let options = query.to_record_options()?;
+  let mut response = create_lookup_player_response(player_name, leaderboard_id, query).await?;
+  response.records_page = Some(record_page::select_records(&mut response.tracker, &options));
+  Ok(response)
+}
+
+async fn create_rivals_response(
+  player_name: &str,
+  leaderboard_id: fetch::LeaderboardId,
+  query: &LookupQuery,
+) -> Result<rivals::Rivals, RuntimeError> {
+  let filter = query.to_match_filter()?;
+  let tracked_player = track_player(player_name, leaderboard_id, &filter).await?;
+  let options = rivals::RivalOptions {
+    min_games: query.min_games.unwrap_or(rivals::DEFAULT_MIN_GAMES),
+    count: query.count.unwrap_or(rivals::DEFAULT_COUNT),
+  };
+  Ok(rivals::rank_rivals(&tracked_player.tracker, &options))
+}
+
+async fn create_sessions_response(
+  player_name: &str,
+  leaderboard_id: fetch::LeaderboardId,
+  query: &LookupQuery,
+) -> Result<Vec<session::Session>, RuntimeError> {
+  let filter = query.to_match_filter()?;
+  let tracked_player = track_player(player_name, leaderboard_id, &filter).await?;
+  let mut sessions = session::detect_sessions(
+    &tracked_player.match_history,
+    &tracked_player.tracker,
+    query.get_session_gap_secs(),
+    tracked_player.current_rating,
+  );
+  sessions.truncate(query.count.unwrap_or(DEFAULT_SESSION_COUNT));
+  Ok(sessions)
+}
+
+async fn create_common_opponents_response(
+  first_profile_id: i32,
+  second_profile_id: i32,
+  leaderboard_id: fetch::LeaderboardId,
+  query: &LookupQuery,
+) -> Result<common_opponents::CommonOpponents, RuntimeError> {
+  let filter = query.to_match_filter()?;
+  let (_, first) = track_profile(first_profile_id, leaderboard_id, &filter).await?;
+  let (_, second) = track_profile(second_profile_id, leaderboard_id, &filter).await?;
+  Ok(common_opponents::compare(&first, &second))
+}
+
+async fn track_batch_player(
+  player: &batch::BatchPlayer,
+  leaderboard_id: fetch::LeaderboardId,
+  filter: &match_filter::MatchFilter,
+) -> Result<(batch::PlayerSummary, player_tracker::PlayerTracker), RuntimeError> {
+  let profile_id = match player {
+    batch::BatchPlayer::ProfileId(profile_id) => *profile_id,
+    batch::BatchPlayer::Name(name) => {
+      lookup_player_with_cache(name, leaderboard_id)
+        .await?
+        .profile_id
+    }
+  };
+  let (match_history, tracker) = track_profile(profile_id, leaderboard_id, filter).await?;
+  Ok((batch::PlayerSummary::new(&match_history, &tracker), tracker))
+}
+
+// players are looked up concurrently, at most concurrency at a time, through the same cache as
+// single lookups. the trackers are those of the players that were found, in the same order
+pub async fn track_batch(
+  players: &Vec<batch::BatchPlayer>,
+  leaderboard_id: fetch::LeaderboardId,
+  filter: &match_filter::MatchFilter,
+  concurrency: usize,
+) -> Result<
+  (
+    Vec<batch::BatchPlayerResult>,
+    Vec<player_tracker::PlayerTracker>,
+  ),
+  RuntimeError,
+> {
+  if players.len() > batch::MAX_PLAYERS {
+    return Err(RuntimeError::new(&format!(
+      "At most {} players can be looked up at once.",
+      batch::MAX_PLAYERS
+    )));
+  }
+  let mut results: Vec<(usize, Result<_, RuntimeError>)> =
+    futures::stream::iter(players.iter().enumerate())
+      .map(
+        |(i, player)| async move { (i, track_batch_player(player, leaderboard_id, filter).await) },
+      )
+      .buffer_unordered(concurrency)
+      .collect()
+      .await;
+  results.sort_by_key(|(i, _)| *i);
+
+  let mut player_results: Vec<batch::BatchPlayerResult> = vec![];
+  let mut trackers: Vec<player_tracker::PlayerTracker> = vec![];
+  for (i, result) in results {
+    match result {
+      Ok((summary, tracker)) => {
+        player_results.push(batch::BatchPlayerResult {
+          query: players[i].describe(),
+          summary: Some(summary),
+          error: None,
+        });
+        trackers.push(tracker);
+      }
+      Err(err) => player_results.push(batch::BatchPlayerResult {
+        query: players[i].describe(),
+        summary: None,
+        error: Some(err.to_string()),
+      }),
+    }
+  }
+  Ok((player_results, trackers))
+}
+
+pub async fn create_batch_response(
+  players: &Vec<batch::BatchPlayer>,
+  leaderboard_id: fetch::LeaderboardId,
+  filter: &match_filter::MatchFilter,
+  concurrency: usize,
+) -> Result<batch::BatchResponse, RuntimeError> {
+  let (player_results, trackers) =
+    track_batch(players, leaderboard_id, filter, concurrency).await?;
+  Ok(batch::BatchResponse {
+    players: player_results,
+    pairs: batch::pairwise_histories(&trackers.iter().collect()),
   })
 }
 
+// every player has to be found, a split without one of them is no use
+pub async fn create_balance_response(
+  players: &Vec<batch::BatchPlayer>,
+  leaderboard_id: fetch::LeaderboardId,
+  filter: &match_filter::MatchFilter,
+  concurrency: usize,
+  options: &balance::BalanceOptions,
+) -> Result<Vec<balance::TeamSplit>, RuntimeError> {
+  if players.len() < balance::MIN_PLAYERS {
+    return Err(RuntimeError::new(&format!(
+      "At least {} players are needed to balance teams.",
+      balance::MIN_PLAYERS
+    )));
+  }
+  let (player_results, trackers) =
+    track_batch(players, leaderboard_id, filter, concurrency).await?;
+  let mut summaries: Vec<&batch::PlayerSummary> = vec![];
+  for result in &player_results {
+    match (&result.summary, &result.error) {
+      (Some(summary), _) => summaries.push(summary),
+      (None, error) => {
+        return Err(RuntimeError::new(&format!(
+          "Could not look up {}: {}",
+          result.query,
+          error.as_deref().unwrap_or("unknown error")
+        )))
+      }
+    }
+  }
+  let balance_players = balance::to_balance_players(&summaries)?;
+  Ok(balance::balance(
+    &balance_players,
+    &trackers.iter().collect(),
+    options,
+  ))
+}
+
+// the roster covers every player whose name has been stored by earlier lookups
+fn create_clan_roster_response(
+  tag: &str,
+  query: &LookupQuery,
+) -> Result<clan::ClanRoster, RuntimeError> {
+  let clan_patterns = query.get_clan_patterns()?;
+  let names = match cache::select_all_player_names() {
+    Ok(names) => names,
+    Err(e) => {
+      return Err(RuntimeError::new(&format!(
+        "Could not read player names: {:?}",
+        e
+      )))
+    }
+  };
+  Ok(clan::build_roster(tag, &names, &clan_patterns))
+}
+
 #[get("/")]
 async fn index(req: HttpRequest) -> impl Responder {
   log_request(&req);
@@ -138,7 +650,11 @@ async fn index(req: HttpRequest) -> impl Responder {
 }
 
 #[get("/lookup/{player_name}/{leaderboard_name}")]
-pub async fn lookup_player(req: HttpRequest, info: web::Path<(String, String)>) -> HttpResponse {
+pub async fn lookup_player(
+  req: HttpRequest,
+  info: web::Path<(String, String)>,
+  query: web::Query<LookupQuery>,
+) -> HttpResponse {
   log_request(&req);
 
   let player_name_arg = &info.0;
@@ -146,7 +662,7 @@ pub async fn lookup_player(req: HttpRequest, info: web::Path<(String, String)>)
 
   let leaderboard_id = format::get_leaderboard_id_from_name(&leaderboard_name_arg);
 
-  match create_lookup_player_response(&player_name_arg, leaderboard_id).await {
+  match create_lookup_player_page_response(&player_name_arg, leaderboard_id, &query).await {
     Ok(response) => HttpResponse::Ok().json(response),
     Err(err) => {
       println!("error getting match history for player {:?}", err);
@@ -154,3 +670,288 @@ pub async fn lookup_player(req: HttpRequest, info: web::Path<(String, String)>)
     }
   }
 }
+
+#[get("/match/{match_id}")]
+pub async fn lookup_match(req: HttpRequest, info: web::Path<(String,)>) -> HttpResponse {
+  log_request(&req);
+
+  let match_id_arg = &info.0;
+
+  match create_match_response(&match_id_arg).await {
+    Ok(response) => HttpResponse::Ok().json(response),
+    Err(err) => {
+      println!("error getting match {:?}", err);
+      HttpResponse::NotFound().json(format!("Error getting match: {:?}", err))
+    }
+  }
+}
+
+#[get("/lookup/{player_name}/{leaderboard_name}/sessions")]
+pub async fn lookup_sessions(
+  req: HttpRequest,
+  info: web::Path<(String, String)>,
+  query: web::Query<LookupQuery>,
+) -> HttpResponse {
+  log_request(&req);
+
+  let player_name_arg = &info.0;
+  let leaderboard_name_arg = &info.1;
+
+  let leaderboard_id = format::get_leaderboard_id_from_name(&leaderboard_name_arg);
+
+  match create_sessions_response(&player_name_arg, leaderboard_id, &query).await {
+    Ok(response) => HttpResponse::Ok().json(response),
+    Err(err) => {
+      println!("error getting sessions for player {:?}", err);
+      HttpResponse::NotFound().json(format!("Error getting sessions for player: {:?}", err))
+    }
+  }
+}
+
+#[get("/lookup/{player_name}/{leaderboard_name}/rivals")]
+pub async fn lookup_rivals(
+  req: HttpRequest,
+  info: web::Path<(String, String)>,
+  query: web::Query<LookupQuery>,
+) -> HttpResponse {
+  log_request(&req);
+
+  let player_name_arg = &info.0;
+  let leaderboard_name_arg = &info.1;
+
+  let leaderboard_id = format::get_leaderboard_id_from_name(&leaderboard_name_arg);
+
+  match create_rivals_response(&player_name_arg, leaderboard_id, &query).await {
+    Ok(response) => HttpResponse::Ok().json(response),
+    Err(err) => {
+      println!("error getting rivals for player {:?}", err);
+      HttpResponse::NotFound().json(format!("Error getting rivals for player: {:?}", err))
+    }
+  }
+}
+
+#[get("/clan/{tag}")]
+pub async fn lookup_clan(
+  req: HttpRequest,
+  info: web::Path<(String,)>,
+  query: web::Query<LookupQuery>,
+) -> HttpResponse {
+  log_request(&req);
+
+  let tag_arg = &info.0;
+
+  match create_clan_roster_response(&tag_arg, &query) {
+    Ok(response) => HttpResponse::Ok().json(response),
+    Err(err) => {
+      println!("error getting clan roster {:?}", err);
+      HttpResponse::NotFound().json(format!("Error getting clan roster: {:?}", err))
+    }
+  }
+}
+
+#[get("/compare/{first_profile_id}/{second_profile_id}/{leaderboard_name}")]
+pub async fn lookup_common_opponents(
+  req: HttpRequest,
+  info: web::Path<(i32, i32, String)>,
+  query: web::Query<LookupQuery>,
+  rate_limiter: web::Data<std::sync::Mutex<rate_limit::RateLimiter>>,
+) -> HttpResponse {
+  log_request(&req);
+  if let Some(response) = rate_limit::charge_players(&req, &rate_limiter, 2) {
+    return response;
+  }
+
+  let first_profile_id_arg = &info.0;
+  let second_profile_id_arg = &info.1;
+  let leaderboard_name_arg = &info.2;
+
+  let leaderboard_id = format::get_leaderboard_id_from_name(&leaderboard_name_arg);
+
+  match create_common_opponents_response(
+    *first_profile_id_arg,
+    *second_profile_id_arg,
+    leaderboard_id,
+    &query,
+  )
+  .await
+  {
+    Ok(response) => HttpResponse::Ok().json(response),
+    Err(err) => {
+      println!("error comparing players {:?}", err);
+      HttpResponse::NotFound().json(format!("Error comparing players: {:?}", err))
+    }
+  }
+}
+
+// server-sent events for a player: match_started, match_finished and records_changed
+#[get("/live/{player_name}/{leaderboard_name}")]
+pub async fn live_events(
+  req: HttpRequest,
+  info: web::Path<(String, String)>,
+  query: web::Query<LookupQuery>,
+  broadcaster: web::Data<std::sync::Mutex<live::Broadcaster>>,
+) -> HttpResponse {
+  log_request(&req);
+
+  let player_name_arg = &info.0;
+  let leaderboard_name_arg = &info.1;
+
+  let leaderboard_id = format::get_leaderboard_id_from_name(&leaderboard_name_arg);
+  let filter = match query.to_match_filter() {
+    Ok(filter) => filter,
+    Err(err) => {
+      return HttpResponse::NotFound().json(format!("Error subscribing to player: {:?}", err))
+    }
+  };
+
+  let player_resp = match lookup_player_with_cache(&player_name_arg, leaderboard_id).await {
+    Ok(player_resp) => player_resp,
+    Err(err) => {
+      println!("error subscribing to player {:?}", err);
+      return HttpResponse::NotFound().json(format!("Error subscribing to player: {:?}", err));
+    }
+  };
+  let rx = broadcaster
+    .lock()
+    .unwrap()
+    .subscribe(player_resp.profile_id, leaderboard_id, filter);
+
+  HttpResponse::Ok()
+    .content_type("text/event-stream")
+    .streaming(rx.map(|event| Ok::<_, actix_web::Error>(event)))
+}
+
+#[get("/overlay/{player_name}/{leaderboard_name}")]
+pub async fn lookup_overlay(
+  req: HttpRequest,
+  info: web::Path<(String, String)>,
+  query: web::Query<LookupQuery>,
+  overlay_query: web::Query<overlay::OverlayQuery>,
+) -> HttpResponse {
+  log_request(&req);
+
+  let player_name_arg = &info.0;
+  let leaderboard_name_arg = &info.1;
+
+  let leaderboard_id = format::get_leaderboard_id_from_name(&leaderboard_name_arg);
+
+  match create_lookup_player_response(&player_name_arg, leaderboard_id, &query).await {
+    Ok(response) => HttpResponse::Ok()
+      .content_type("text/html; charset=utf-8")
+      .body(overlay::render(&response, &overlay_query)),
+    Err(err) => {
+      println!("error getting overlay for player {:?}", err);
+      HttpResponse::NotFound()
+        .content_type("text/html; charset=utf-8")
+        .body(overlay::render_error(
+          &player_name_arg,
+          &err.to_string(),
+          &overlay_query,
+        ))
+    }
+  }
+}
+
+#[post("/lookup/batch")]
+pub async fn lookup_batch(
+  req: HttpRequest,
+  body: web::Json<batch::BatchRequest>,
+  query: web::Query<LookupQuery>,
+  rate_limiter: web::Data<std::sync::Mutex<rate_limit::RateLimiter>>,
+) -> HttpResponse {
+  log_request(&req);
+  if let Some(response) = rate_limit::charge_players(&req, &rate_limiter, body.players.len()) {
+    return response;
+  }
+
+  let leaderboard_id =
+    format::get_leaderboard_id_from_name(body.leaderboard.as_deref().unwrap_or("team"));
+  let filter = match query.to_match_filter() {
+    Ok(filter) => filter,
+    Err(err) => return HttpResponse::NotFound().json(format!("Error in batch lookup: {:?}", err)),
+  };
+
+  match create_batch_response(
+    &body.players,
+    leaderboard_id,
+    &filter,
+    batch::get_concurrency(body.concurrency),
+  )
+  .await
+  {
+    Ok(response) => HttpResponse::Ok().json(response),
+    Err(err) => {
+      println!("error in batch lookup {:?}", err);
+      HttpResponse::NotFound().json(format!("Error in batch lookup: {:?}", err))
+    }
+  }
+}
+
+#[post("/balance")]
+pub async fn lookup_balance(
+  req: HttpRequest,
+  body: web::Json<balance::BalanceRequest>,
+  query: web::Query<LookupQuery>,
+  rate_limiter: web::Data<std::sync::Mutex<rate_limit::RateLimiter>>,
+) -> HttpResponse {
+  log_request(&req);
+  if let Some(response) = rate_limit::charge_players(&req, &rate_limiter, body.players.len()) {
+    return response;
+  }
+
+  let leaderboard_id =
+    format::get_leaderboard_id_from_name(body.leaderboard.as_deref().unwrap_or("team"));
+  let filter = match query.to_match_filter() {
+    Ok(filter) => filter,
+    Err(err) => return HttpResponse::NotFound().json(format!("Error balancing teams: {:?}", err)),
+  };
+  let options = balance::BalanceOptions {
+    rival_penalty: body.rival_penalty.unwrap_or(0.0),
+    teammate_bonus: body.teammate_bonus.unwrap_or(0.0),
+    count: body.count.unwrap_or(balance::DEFAULT_COUNT),
+  };
+
+  match create_balance_response(
+    &body.players,
+    leaderboard_id,
+    &filter,
+    batch::get_concurrency(body.concurrency),
+    &options,
+  )
+  .await
+  {
+    Ok(response) => HttpResponse::Ok().json(response),
+    Err(err) => {
+      println!("error balancing teams {:?}", err);
+      HttpResponse::NotFound().json(format!("Error balancing teams: {:?}", err))
+    }
+  }
+}
+
+#[get("/api/v1/players/{player_name}/{leaderboard_name}")]
+pub async fn api_v1_lookup_player(
+  req: HttpRequest,
+  info: web::Path<(String, String)>,
+  query: web::Query<LookupQuery>,
+) -> HttpResponse {
+  log_request(&req);
+
+  let player_name_arg = &info.0;
+  let leaderboard_name_arg = &info.1;
+
+  let leaderboard_id = format::get_leaderboard_id_from_name(&leaderboard_name_arg);
+
+  match create_lookup_player_response(&player_name_arg, leaderboard_id, &query).await {
+    Ok(response) => HttpResponse::Ok().json(api_v1::PlayerLookupV1::new(&response)),
+    Err(err) => {
+      println!("error getting match history for player {:?}", err);
+      HttpResponse::NotFound().json(format!("Error getting match history for player: {:?}", err))
+    }
+  }
+}
+
+#[get("/api/v1/openapi.json")]
+pub async fn api_v1_openapi(req: HttpRequest) -> HttpResponse {
+  log_request(&req);
+  HttpResponse::Ok().json(api_v1::openapi_document())
+}
@@ -0,0 +1,229 @@
+use actix_web::web::Bytes;
+use futures::channel::mpsc;
+use std::sync::{Arc, Mutex};
+
+use crate::error::RuntimeError;
+use crate::fetch;
+use crate::match_filter;
+use crate::outcome;
+use crate::player_tracker;
+use crate::prediction;
+use crate::server::endpoints;
+use crate::watch;
+
+// one upstream request per watched player every interval, however many viewers it has
+pub const POLL_INTERVAL_SECS: u64 = 30;
+
+#[derive(serde::Serialize)]
+struct MatchStartedEvent<'a> {
+  game: &'a fetch::MatchHistoryGameResponse,
+  prediction: prediction::Prediction,
+}
+
+#[derive(serde::Serialize)]
+struct MatchFinishedEvent<'a> {
+  match_id: &'a str,
+  outcome: Option<&'a outcome::InferredOutcome>,
+}
+
+#[derive(serde::Serialize)]
+struct OpponentRecord {
+  profile_id: i32,
+  name: String,
+  wins_against: i32,
+  losses_to: i32,
+}
+
+#[derive(serde::Serialize)]
+struct RecordsChangedEvent {
+  wins: i32,
+  losses: i32,
+  // the records against the opponents of the game that just finished
+  opponents: Vec<OpponentRecord>,
+}
+
+fn to_event<T: serde::Serialize>(kind: &str, data: &T) -> Bytes {
+  let json = serde_json::to_string(data).unwrap_or(String::from("null"));
+  Bytes::from(format!("event: {}\ndata: {}\n\n", kind, json))
+}
+
+// viewers with the same filter share one subscription
+type SubscriptionKey = (i32, fetch::LeaderboardId, match_filter::MatchFilter);
+
+#[derive(Clone)]
+struct PlayerState {
+  watcher: watch::Watcher,
+  // sent again to viewers who subscribe while the game is in progress
+  match_started_event: Option<Bytes>,
+  wins_losses: Option<(i32, i32)>,
+}
+
+struct Subscription {
+  clients: Vec<mpsc::UnboundedSender<Bytes>>,
+  state: PlayerState,
+}
+
+#[derive(Default)]
+pub struct Broadcaster {
+  subscriptions: std::collections::HashMap<SubscriptionKey, Subscription>,
+}
+
+impl Broadcaster {
+  pub fn new() -> Broadcaster {
+    Broadcaster::default()
+  }
+  pub fn subscribe(
+    &mut self,
+    profile_id: i32,
+    leaderboard_id: fetch::LeaderboardId,
+    filter: match_filter::MatchFilter,
+  ) -> mpsc::UnboundedReceiver<Bytes> {
+    let (tx, rx) = mpsc::unbounded();
+    let subscription = self
+      .subscriptions
+      .entry((profile_id, leaderboard_id, filter.clone()))
+      .or_insert(Subscription {
+        clients: vec![],
+        state: PlayerState {
+          watcher: watch::Watcher::new(profile_id, leaderboard_id, filter, None),
+          match_started_event: None,
+          wins_losses: None,
+        },
+      });
+    let _ = tx.unbounded_send(to_event(
+      "subscribed",
+      &serde_json::json!({ "profile_id": profile_id, "leaderboard_id": leaderboard_id as i32 }),
+    ));
+    if let Some(event) = &subscription.state.match_started_event {
+      let _ = tx.unbounded_send(event.clone());
+    }
+    subscription.clients.push(tx);
+    rx
+  }
+  // drops viewers who have disconnected, and players nobody is watching any more
+  fn ping(&mut self) {
+    for subscription in self.subscriptions.values_mut() {
+      subscription
+        .clients
+        .retain(|client| client.unbounded_send(Bytes::from(": ping\n\n")).is_ok());
+    }
+    self
+      .subscriptions
+      .retain(|_, subscription| subscription.clients.len() > 0);
+  }
+  fn update(&mut self, key: SubscriptionKey, state: PlayerState, events: Vec<Bytes>) {
+    if let Some(subscription) = self.subscriptions.get_mut(&key) {
+      subscription.state = state;
+      for event in events {
+        subscription
+          .clients
+          .retain(|client| client.unbounded_send(event.clone()).is_ok());
+      }
+    }
+  }
+}
+
+// the cached match history is refreshed first, since the game that just finished is not in it
+async fn track(
+  profile_id: i32,
+  leaderboard_id: fetch::LeaderboardId,
+  filter: match_filter::MatchFilter,
+) -> Result<player_tracker::PlayerTracker, RuntimeError> {
+  endpoints::refresh_match_history(profile_id).await?;
+  let (_, tracker) = endpoints::track_profile(profile_id, leaderboard_id, &filter).await?;
+  Ok(tracker)
+}
+
+async fn poll_player(mut state: PlayerState) -> (PlayerState, Vec<Bytes>) {
+  let mut events: Vec<Bytes> = vec![];
+  let profile_id = state.watcher.profile_id;
+  let update = match state.watcher.poll(track).await {
+    Ok(update) => update,
+    Err(e) => {
+      println!(
+        "[LIVE] Could not update the game of {}: {:?}",
+        profile_id, e
+      );
+      return (state, events);
+    }
+  };
+  let tracker = match &update.tracker {
+    Some(tracker) => tracker,
+    None => return (state, events),
+  };
+
+  if let Some(game) = &update.finished {
+    events.push(to_event(
+      "match_finished",
+      &MatchFinishedEvent {
+        match_id: game.get_match_id(),
+        outcome: tracker.get_outcome(game.get_match_id()),
+      },
+    ));
+    if state.wins_losses != Some((tracker.wins, tracker.losses)) {
+      let opponents = game
+        .get_opposing_team(profile_id)
+        .iter()
+        .map(|enemy_player| {
+          let (wins_against, losses_to) =
+            tracker.get_win_loss_record(enemy_player.get_profile_id());
+          OpponentRecord {
+            profile_id: enemy_player.get_profile_id(),
+            name: enemy_player.get_name(),
+            wins_against,
+            losses_to,
+          }
+        })
+        .collect();
+      events.push(to_event(
+        "records_changed",
+        &RecordsChangedEvent {
+          wins: tracker.wins,
+          losses: tracker.losses,
+          opponents,
+        },
+      ));
+    }
+    state.match_started_event = None;
+  }
+
+  if let Some(game) = &update.started {
+    let event = to_event(
+      "match_started",
+      &MatchStartedEvent {
+        game,
+        prediction: prediction::predict_game(game, tracker),
+      },
+    );
+    events.push(event.clone());
+    state.match_started_event = Some(event);
+  }
+  state.wins_losses = Some((tracker.wins, tracker.losses));
+  (state, events)
+}
+
+async fn poll(broadcaster: &Arc<Mutex<Broadcaster>>) {
+  // the lock is not held while waiting on aoe2.net
+  let subscriptions: Vec<(SubscriptionKey, PlayerState)> = {
+    let mut broadcaster = broadcaster.lock().unwrap();
+    broadcaster.ping();
+    broadcaster
+      .subscriptions
+      .iter()
+      .map(|(key, subscription)| (key.clone(), subscription.state.clone()))
+      .collect()
+  };
+  for (key, state) in subscriptions {
+    let (state, events) = poll_player(state).await;
+    broadcaster.lock().unwrap().update(key, state, events);
+  }
+}
+
+pub fn start_poller(broadcaster: Arc<Mutex<Broadcaster>>) {
+  actix_rt::spawn(async move {
+    loop {
+      actix_rt::time::delay_for(std::time::Duration::from_secs(POLL_INTERVAL_SECS)).await;
+      poll(&broadcaster).await;
+    }
+  });
+}
@@ -1,5 +1,9 @@
+pub mod api_v1;
 pub mod endpoints;
 pub mod server;
 pub mod cache;
+pub mod live;
+pub mod overlay;
+pub mod rate_limit;
 
 pub use server::listen as listen;
\ No newline at end of file
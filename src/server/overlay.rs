@@ -0,0 +1,169 @@
+use crate::fetch;
+use crate::player_tracker;
+use crate::server::endpoints::LookupPlayerResponse;
+
+pub const DEFAULT_REFRESH_SECS: u64 = 30;
+
+#[derive(serde::Deserialize)]
+pub struct OverlayQuery {
+  theme: Option<String>,
+  size: Option<String>,
+  refresh: Option<u64>,
+}
+
+// unknown themes and sizes fall back to the defaults, so a typo in an OBS source still shows
+// something
+fn get_colors(theme: &str) -> (&'static str, &'static str, &'static str) {
+  // background, text, muted text
+  match theme {
+    "light" => ("#f4f4f4", "#1a1a1a", "#666666"),
+    "transparent" => ("transparent", "#ffffff", "#cccccc"),
+    _ => ("#1a1a1a", "#f4f4f4", "#999999"),
+  }
+}
+
+fn get_font_size(size: &str) -> u32 {
+  match size {
+    "small" => 14,
+    "large" => 24,
+    _ => 18,
+  }
+}
+
+fn escape_html(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&#39;")
+}
+
+fn format_streak(streak: i32) -> String {
+  if streak > 0 {
+    format!("W{}", streak)
+  } else if streak < 0 {
+    format!("L{}", -streak)
+  } else {
+    String::from("-")
+  }
+}
+
+fn render_player_row(
+  player: &fetch::MatchHistoryPlayerResponse,
+  tracker: &player_tracker::PlayerTracker,
+  is_opponent: bool,
+) -> String {
+  let profile_id = player.get_profile_id();
+  let record = if profile_id == tracker.profile_id {
+    String::default()
+  } else if is_opponent {
+    let (wins, losses) = tracker.get_win_loss_record(profile_id);
+    format!(
+      "{}-{} <span class=\"muted\">{}</span>",
+      wins,
+      losses,
+      format_streak(tracker.get_streak_against(profile_id))
+    )
+  } else {
+    let (wins, losses) = tracker.get_teammate_record(profile_id);
+    format!("{}-{} <span class=\"muted\">with</span>", wins, losses)
+  };
+  format!(
+    "<tr><td>{}</td><td class=\"rating\">{}</td><td class=\"record\">{}</td></tr>\n",
+    escape_html(&player.get_name()),
+    player.get_rating(),
+    record
+  )
+}
+
+fn render_team(
+  title: &str,
+  players: &Vec<&fetch::MatchHistoryPlayerResponse>,
+  tracker: &player_tracker::PlayerTracker,
+  is_opponent: bool,
+) -> String {
+  let mut ret = format!("<table>\n<tr><th colspan=\"3\">{}</th></tr>\n", title);
+  for player in players {
+    ret += &render_player_row(player, tracker, is_opponent);
+  }
+  ret += "</table>\n";
+  return ret;
+}
+
+// the page keeps refreshing on errors too, so the overlay recovers on its own once the lookup
+// works again
+fn render_page(title: &str, header: &str, body: &str, query: &OverlayQuery) -> String {
+  let (background, text, muted) = get_colors(query.theme.as_deref().unwrap_or("dark"));
+  let font_size = get_font_size(query.size.as_deref().unwrap_or("medium"));
+  let refresh_secs = query.refresh.unwrap_or(DEFAULT_REFRESH_SECS).max(5);
+
+  format!(
+    r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="{refresh_secs}">
+<title>{title}</title>
+<style>
+body {{ margin: 0; padding: 8px; background: {background}; color: {text}; font-family: sans-serif; font-size: {font_size}px; }}
+table {{ border-collapse: collapse; margin-bottom: 8px; }}
+th {{ text-align: left; color: {muted}; font-weight: normal; }}
+td {{ padding: 0 12px 0 0; }}
+.rating, .record {{ text-align: right; }}
+.muted {{ color: {muted}; }}
+</style>
+</head>
+<body>
+<div class="muted">{header}</div>
+{body}</body>
+</html>
+"#,
+    refresh_secs = refresh_secs,
+    title = title,
+    header = header,
+    background = background,
+    text = text,
+    muted = muted,
+    font_size = font_size,
+    body = body
+  )
+}
+
+pub fn render(response: &LookupPlayerResponse, query: &OverlayQuery) -> String {
+  let game = &response.most_recent_game;
+  let tracker = &response.tracker;
+
+  let mut my_team = game.get_my_team(tracker.profile_id);
+  if let Some(me) = game.get_player_by_profile_id(tracker.profile_id) {
+    my_team.insert(0, me);
+  }
+  let mut body = render_team("Team", &my_team, tracker, false);
+  for (i, other_team) in game
+    .get_opposing_teams(tracker.profile_id)
+    .iter()
+    .enumerate()
+  {
+    body += &render_team(&format!("Opponents {}", i + 1), other_team, tracker, true);
+  }
+  let status = match (&game.finished, &response.prediction) {
+    (None, Some(prediction)) => {
+      format!("In game, {:.0}% to win", prediction.win_probability * 100.0)
+    }
+    (None, None) => String::from("In game"),
+    (Some(_), _) => String::from("Last game"),
+  };
+
+  let player_name = escape_html(&response.player_name);
+  let header = format!(
+    "{} {}-{} - {}",
+    player_name, tracker.wins, tracker.losses, status
+  );
+  render_page(&player_name, &header, &body, query)
+}
+
+pub fn render_error(player_name: &str, message: &str, query: &OverlayQuery) -> String {
+  let player_name = escape_html(player_name);
+  let header = format!("{} - {}", player_name, escape_html(message));
+  render_page(&player_name, &header, "", query)
+}
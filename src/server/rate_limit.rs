@@ -0,0 +1,383 @@
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{Error, HttpRequest, HttpResponse};
+use futures::future::{ok, Either, Ready};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+pub const DEFAULT_REQUESTS: u32 = 30;
+pub const DEFAULT_WINDOW_SECS: u64 = 60;
+
+// the page and its assets are cheap to serve, only the lookups behind them are limited
+const EXEMPT_PATH_PREFIXES: [&str; 2] = ["/static", "/public"];
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+  // requests a client can make per window, 0 turns rate limiting off
+  pub requests: u32,
+  pub window_secs: u64,
+  // clients that are never limited, by ip address
+  pub allowlist: std::collections::HashSet<String>,
+  // behind a proxy like the heroku router every request comes from the proxy, which adds the
+  // client's address as the last entry of x-forwarded-for. only trusted when set, or on heroku
+  // where DYNO is set, since a client talking to the server directly can put anything there
+  pub trust_forwarded_for: bool,
+}
+
+impl RateLimitConfig {
+  // RATE_LIMIT_REQUESTS, RATE_LIMIT_WINDOW_SECS, RATE_LIMIT_ALLOWLIST (comma separated) and
+  // RATE_LIMIT_TRUST_FORWARDED_FOR
+  pub fn from_env() -> RateLimitConfig {
+    let get_var = |name: &str| std::env::var(name).ok();
+    RateLimitConfig {
+      requests: get_var("RATE_LIMIT_REQUESTS")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_REQUESTS),
+      window_secs: get_var("RATE_LIMIT_WINDOW_SECS")
+        .and_then(|value| value.parse().ok())
+        .filter(|window_secs| *window_secs > 0)
+        .unwrap_or(DEFAULT_WINDOW_SECS),
+      allowlist: get_var("RATE_LIMIT_ALLOWLIST")
+        .unwrap_or_default()
+        .split(',')
+        .map(|ip| String::from(ip.trim()))
+        .filter(|ip| ip != "")
+        .collect(),
+      trust_forwarded_for: get_var("RATE_LIMIT_TRUST_FORWARDED_FOR")
+        .map_or(get_var("DYNO").is_some(), |value| {
+          value == "true" || value == "1"
+        }),
+    }
+  }
+  pub fn format(&self) -> String {
+    if self.requests == 0 {
+      return String::from("off");
+    }
+    format!(
+      "{} requests per {}s, {} allowlisted{}",
+      self.requests,
+      self.window_secs,
+      self.allowlist.len(),
+      if self.trust_forwarded_for {
+        ", trusting x-forwarded-for"
+      } else {
+        ""
+      }
+    )
+  }
+}
+
+// each client has a bucket of requests that refills evenly over the window, so a burst of the full
+// budget is allowed but after that requests are spread out
+struct Bucket {
+  tokens: f64,
+  last_updated: std::time::Instant,
+}
+
+pub struct RateLimiter {
+  config: RateLimitConfig,
+  buckets: std::collections::HashMap<String, Bucket>,
+  last_pruned: std::time::Instant,
+}
+
+impl RateLimiter {
+  pub fn new(config: RateLimitConfig) -> RateLimiter {
+    RateLimiter {
+      config,
+      buckets: std::collections::HashMap::new(),
+      last_pruned: std::time::Instant::now(),
+    }
+  }
+  fn get_refill_per_sec(&self) -> f64 {
+    self.config.requests as f64 / self.config.window_secs as f64
+  }
+  // buckets that have refilled completely are the same as new ones
+  fn prune(&mut self, now: std::time::Instant) {
+    let window = std::time::Duration::from_secs(self.config.window_secs);
+    if now.duration_since(self.last_pruned) < window {
+      return;
+    }
+    self
+      .buckets
+      .retain(|_, bucket| now.duration_since(bucket.last_updated) < window);
+    self.last_pruned = now;
+  }
+  // the number of seconds until the client can make another request if it is over its limit. a
+  // request costs more than one when it looks up several players, but never more than the budget
+  pub fn check(&mut self, client: &str, cost: u32) -> Option<u64> {
+    self.check_at(client, cost, std::time::Instant::now())
+  }
+  fn check_at(&mut self, client: &str, cost: u32, now: std::time::Instant) -> Option<u64> {
+    if self.config.requests == 0 || self.config.allowlist.contains(client) {
+      return None;
+    }
+    self.prune(now);
+    let capacity = self.config.requests as f64;
+    let refill_per_sec = self.get_refill_per_sec();
+    let bucket = self.buckets.entry(String::from(client)).or_insert(Bucket {
+      tokens: capacity,
+      last_updated: now,
+    });
+    let elapsed_secs = now.duration_since(bucket.last_updated).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed_secs * refill_per_sec).min(capacity);
+    bucket.last_updated = now;
+    let cost = (cost as f64).min(capacity);
+    if bucket.tokens >= cost {
+      bucket.tokens -= cost;
+      None
+    } else {
+      Some(((cost - bucket.tokens) / refill_per_sec).ceil().max(1.0) as u64)
+    }
+  }
+}
+
+fn get_client(
+  headers: &actix_web::http::HeaderMap,
+  peer_addr: Option<std::net::SocketAddr>,
+  trust_forwarded_for: bool,
+) -> String {
+  if trust_forwarded_for {
+    let forwarded_for = headers
+      .get("x-forwarded-for")
+      .and_then(|value| value.to_str().ok())
+      .and_then(|value| value.split(',').last())
+      .map(|ip| ip.trim());
+    if let Some(ip) = forwarded_for {
+      if ip != "" {
+        return String::from(ip);
+      }
+    }
+  }
+  match peer_addr {
+    Some(addr) => addr.ip().to_string(),
+    None => String::from("unknown"),
+  }
+}
+
+fn too_many_requests(retry_after_secs: u64) -> HttpResponse {
+  HttpResponse::TooManyRequests()
+    .header("Retry-After", retry_after_secs.to_string())
+    .json(format!(
+      "Too many requests, try again in {} seconds",
+      retry_after_secs
+    ))
+}
+
+// the middleware has already taken one request from the client, endpoints looking up several
+// players take the rest here. none if the client is within its limit
+pub fn charge_players(
+  req: &HttpRequest,
+  limiter: &Mutex<RateLimiter>,
+  player_count: usize,
+) -> Option<HttpResponse> {
+  if player_count <= 1 {
+    return None;
+  }
+  let mut limiter = limiter.lock().unwrap();
+  let client = get_client(
+    req.headers(),
+    req.peer_addr(),
+    limiter.config.trust_forwarded_for,
+  );
+  let retry_after_secs = limiter.check(&client, player_count as u32 - 1)?;
+  println!(
+    "[SRV] Rate limited {} on {} for {} players",
+    client,
+    req.path(),
+    player_count
+  );
+  Some(too_many_requests(retry_after_secs))
+}
+
+// middleware answering 429 with a Retry-After header to clients over their limit. the limiter is
+// shared so that the limit holds across all the server's workers
+pub struct RateLimit {
+  limiter: Arc<Mutex<RateLimiter>>,
+}
+
+impl RateLimit {
+  pub fn new(limiter: Arc<Mutex<RateLimiter>>) -> RateLimit {
+    RateLimit { limiter }
+  }
+}
+
+impl<S> Transform<S> for RateLimit
+where
+  S: Service<
+    Request = ServiceRequest,
+    Response = ServiceResponse<actix_web::body::Body>,
+    Error = Error,
+  >,
+  S::Future: 'static,
+{
+  type Request = ServiceRequest;
+  type Response = ServiceResponse<actix_web::body::Body>;
+  type Error = Error;
+  type InitError = ();
+  type Transform = RateLimitMiddleware<S>;
+  type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+  fn new_transform(&self, service: S) -> Self::Future {
+    ok(RateLimitMiddleware {
+      service,
+      limiter: self.limiter.clone(),
+    })
+  }
+}
+
+pub struct RateLimitMiddleware<S> {
+  service: S,
+  limiter: Arc<Mutex<RateLimiter>>,
+}
+
+impl<S> Service for RateLimitMiddleware<S>
+where
+  S: Service<
+    Request = ServiceRequest,
+    Response = ServiceResponse<actix_web::body::Body>,
+    Error = Error,
+  >,
+  S::Future: 'static,
+{
+  type Request = ServiceRequest;
+  type Response = ServiceResponse<actix_web::body::Body>;
+  type Error = Error;
+  type Future = Either<Ready<Result<Self::Response, Self::Error>>, S::Future>;
+
+  fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+    self.service.poll_ready(cx)
+  }
+
+  fn call(&mut self, req: ServiceRequest) -> Self::Future {
+    let path = req.path();
+    if path == "/"
+      || EXEMPT_PATH_PREFIXES
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+    {
+      return Either::Right(self.service.call(req));
+    }
+    let retry_after_secs = {
+      let mut limiter = self.limiter.lock().unwrap();
+      let client = get_client(
+        req.headers(),
+        req.peer_addr(),
+        limiter.config.trust_forwarded_for,
+      );
+      let retry_after_secs = limiter.check(&client, 1);
+      if retry_after_secs.is_some() {
+        println!("[SRV] Rate limited {} on {}", client, path);
+      }
+      retry_after_secs
+    };
+    match retry_after_secs {
+      None => Either::Right(self.service.call(req)),
+      Some(retry_after_secs) => {
+        Either::Left(ok(req.into_response(too_many_requests(retry_after_secs))))
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::{Duration, Instant};
+
+  fn limiter(requests: u32, window_secs: u64, allowlist: &[&str]) -> RateLimiter {
+    RateLimiter::new(RateLimitConfig {
+      requests,
+      window_secs,
+      allowlist: allowlist.iter().map(|ip| String::from(*ip)).collect(),
+      trust_forwarded_for: false,
+    })
+  }
+
+  #[test]
+  fn allows_a_burst_then_refills_over_the_window() {
+    // one request every 5 seconds after the first 2
+    let mut limiter = limiter(2, 10, &[]);
+    let start = Instant::now();
+    assert_eq!(limiter.check_at("1.2.3.4", 1, start), None);
+    assert_eq!(limiter.check_at("1.2.3.4", 1, start), None);
+    assert_eq!(limiter.check_at("1.2.3.4", 1, start), Some(5));
+    assert_eq!(
+      limiter.check_at("1.2.3.4", 1, start + Duration::from_secs(2)),
+      Some(3)
+    );
+    assert_eq!(
+      limiter.check_at("1.2.3.4", 1, start + Duration::from_secs(5)),
+      None
+    );
+    assert_eq!(
+      limiter.check_at("1.2.3.4", 1, start + Duration::from_secs(5)),
+      Some(5)
+    );
+    // other clients have their own bucket
+    assert_eq!(limiter.check_at("5.6.7.8", 1, start), None);
+  }
+
+  #[test]
+  fn refills_no_more_than_the_budget() {
+    let mut limiter = limiter(2, 10, &[]);
+    let start = Instant::now();
+    let later = start + Duration::from_secs(1000);
+    assert_eq!(limiter.check_at("1.2.3.4", 1, start), None);
+    assert_eq!(limiter.check_at("1.2.3.4", 1, later), None);
+    assert_eq!(limiter.check_at("1.2.3.4", 1, later), None);
+    assert_eq!(limiter.check_at("1.2.3.4", 1, later), Some(5));
+  }
+
+  #[test]
+  fn caps_the_cost_at_the_budget() {
+    let mut limiter = limiter(3, 30, &[]);
+    let start = Instant::now();
+    // a lookup of more players than the budget is allowed with a full bucket, and empties it
+    assert_eq!(limiter.check_at("1.2.3.4", 10, start), None);
+    assert_eq!(limiter.check_at("1.2.3.4", 1, start), Some(10));
+    assert_eq!(
+      limiter.check_at("1.2.3.4", 10, start + Duration::from_secs(10)),
+      Some(20)
+    );
+  }
+
+  #[test]
+  fn waits_at_least_a_second() {
+    let mut limiter = limiter(60, 1, &[]);
+    let start = Instant::now();
+    assert_eq!(limiter.check_at("1.2.3.4", 60, start), None);
+    assert_eq!(limiter.check_at("1.2.3.4", 1, start), Some(1));
+  }
+
+  #[test]
+  fn never_limits_allowlisted_clients() {
+    let mut limiter = limiter(1, 60, &["10.0.0.1"]);
+    let start = Instant::now();
+    for _ in 0..10 {
+      assert_eq!(limiter.check_at("10.0.0.1", 5, start), None);
+    }
+    assert_eq!(limiter.check_at("10.0.0.2", 1, start), None);
+    assert_eq!(limiter.check_at("10.0.0.2", 1, start), Some(60));
+  }
+
+  #[test]
+  fn zero_requests_turns_limiting_off() {
+    let mut limiter = limiter(0, 60, &[]);
+    let start = Instant::now();
+    for _ in 0..10 {
+      assert_eq!(limiter.check_at("1.2.3.4", 1, start), None);
+    }
+  }
+
+  #[test]
+  fn prunes_full_buckets() {
+    let mut limiter = limiter(2, 10, &[]);
+    let start = Instant::now();
+    assert_eq!(limiter.check_at("1.2.3.4", 1, start), None);
+    assert_eq!(
+      limiter.check_at("5.6.7.8", 1, start + Duration::from_secs(10)),
+      None
+    );
+    assert_eq!(limiter.buckets.len(), 1);
+    assert!(limiter.buckets.contains_key("5.6.7.8"));
+  }
+}
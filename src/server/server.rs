@@ -5,6 +5,8 @@ use actix_files as fs;
 use crate::server::endpoints::*;
 
 use crate::server::cache::init_cache;
+use crate::server::live;
+use crate::server::rate_limit;
 
 #[actix_rt::main]
 pub async fn listen() -> std::io::Result<()> {
@@ -21,16 +23,41 @@ pub async fn listen() -> std::io::Result<()> {
     }
   }
 
+  let broadcaster = actix_web::web::Data::new(std::sync::Mutex::new(live::Broadcaster::new()));
+  live::start_poller(broadcaster.clone().into_inner());
+
+  let rate_limit_config = rate_limit::RateLimitConfig::from_env();
+  println!("[SRV] Rate limit: {}", rate_limit_config.format());
+  let rate_limiter = actix_web::web::Data::new(std::sync::Mutex::new(
+    rate_limit::RateLimiter::new(rate_limit_config),
+  ));
+
   let addr = format!("0.0.0.0:{}", port);
 
   println!("[SRV] Listening on: {}", addr);
-  HttpServer::new(|| {
+  HttpServer::new(move || {
     App::new()
+      .wrap(rate_limit::RateLimit::new(
+        rate_limiter.clone().into_inner(),
+      ))
+      .app_data(broadcaster.clone())
+      .app_data(rate_limiter.clone())
       .service(index)
       .service(fs::Files::new("/public", "ui/public").index_file("index.html"))
       .service(fs::Files::new("/static/js", "ui/build/static/js").index_file("index.html"))
       .service(fs::Files::new("/static", "ui/build").index_file("index.html"))
+      .service(lookup_batch)
+      .service(lookup_balance)
       .service(lookup_player)
+      .service(lookup_sessions)
+      .service(lookup_rivals)
+      .service(lookup_clan)
+      .service(lookup_common_opponents)
+      .service(lookup_match)
+      .service(live_events)
+      .service(lookup_overlay)
+      .service(api_v1_lookup_player)
+      .service(api_v1_openapi)
   })
   .bind(addr)?
   .run()
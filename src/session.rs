@@ -0,0 +1,169 @@
+use crate::fetch;
+use crate::format;
+use crate::player_tracker;
+
+// a break longer than this between one game finishing and the next starting ends a session
+pub const DEFAULT_SESSION_GAP_SECS: i64 = 30 * 60;
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct SessionPlayer {
+  pub profile_id: i32,
+  pub name: String,
+  pub games: i32,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct Session {
+  pub started: i64,
+  pub finished: i64,
+  pub games: i32,
+  pub wins: i32,
+  pub losses: i32,
+  pub rating_before: Option<i32>,
+  pub rating_after: Option<i32>,
+  pub net_rating_change: Option<i32>,
+  pub match_ids: Vec<String>,
+  pub opponents: Vec<SessionPlayer>,
+  pub teammates: Vec<SessionPlayer>,
+}
+
+fn add_session_player(
+  players: &mut Vec<SessionPlayer>,
+  player: &fetch::MatchHistoryPlayerResponse,
+) {
+  match players
+    .iter_mut()
+    .find(|p| p.profile_id == player.get_profile_id())
+  {
+    Some(p) => p.games += 1,
+    None => players.push(SessionPlayer {
+      profile_id: player.get_profile_id(),
+      name: player.get_name(),
+      games: 1,
+    }),
+  }
+}
+
+fn format_players(players: &Vec<SessionPlayer>) -> String {
+  let names: Vec<String> = players
+    .iter()
+    .map(|p| format!("{} ({})", p.name, p.games))
+    .collect();
+  return names.join(", ");
+}
+
+impl Session {
+  fn new(game: &fetch::MatchHistoryGameResponse) -> Session {
+    Session {
+      started: game.started,
+      finished: game.started,
+      games: 0,
+      wins: 0,
+      losses: 0,
+      rating_before: None,
+      rating_after: None,
+      net_rating_change: None,
+      match_ids: vec![],
+      opponents: vec![],
+      teammates: vec![],
+    }
+  }
+  fn add_game(
+    &mut self,
+    game: &fetch::MatchHistoryGameResponse,
+    tracker: &player_tracker::PlayerTracker,
+  ) {
+    let profile_id = tracker.profile_id;
+    self.games += 1;
+    self.finished = game_end(game);
+    self.match_ids.push(String::from(game.get_match_id()));
+    if self.rating_before.is_none() {
+      self.rating_before = game
+        .get_player_by_profile_id(profile_id)
+        .and_then(|player| player.rating);
+    }
+    match tracker
+      .get_outcome(game.get_match_id())
+      .and_then(|outcome| outcome.is_win)
+    {
+      Some(true) => self.wins += 1,
+      Some(false) => self.losses += 1,
+      None => (),
+    }
+    for enemy_player in game.get_opposing_team(profile_id) {
+      add_session_player(&mut self.opponents, enemy_player);
+    }
+    for ally_player in game.get_my_team(profile_id) {
+      add_session_player(&mut self.teammates, ally_player);
+    }
+  }
+  fn set_rating_after(&mut self, rating_after: Option<i32>) {
+    self.rating_after = rating_after;
+    self.net_rating_change = match (self.rating_before, rating_after) {
+      (Some(before), Some(after)) => Some(after - before),
+      _ => None,
+    };
+  }
+  pub fn format(&self) -> String {
+    let rating_change = match self.net_rating_change {
+      Some(change) => format!("{:+}", change),
+      None => String::from("unknown"),
+    };
+    let mut ret = format!(
+      "{} to {}: {} games, {} wins, {} losses, rating change {}\n",
+      format::timestamp_to_date(self.started),
+      format::timestamp_to_date(self.finished),
+      self.games,
+      self.wins,
+      self.losses,
+      rating_change
+    );
+    if self.teammates.len() > 0 {
+      ret += &format!("  Teammates: {}\n", format_players(&self.teammates));
+    }
+    ret += &format!("  Opponents: {}\n", format_players(&self.opponents));
+    return ret;
+  }
+}
+
+fn game_end(game: &fetch::MatchHistoryGameResponse) -> i64 {
+  match game.finished {
+    Some(finished) => finished as i64,
+    None => game.started,
+  }
+}
+
+// sessions are returned newest first, like the match history. current_rating is used as the
+// rating after the most recent session, since there is no later game to read it from, and should
+// be none when the filter left out later games
+pub fn detect_sessions(
+  match_history: &Vec<fetch::MatchHistoryGameResponse>,
+  tracker: &player_tracker::PlayerTracker,
+  gap_secs: i64,
+  current_rating: Option<i32>,
+) -> Vec<Session> {
+  let mut sessions: Vec<Session> = vec![];
+  for game in match_history.iter().rev() {
+    let rating = game
+      .get_player_by_profile_id(tracker.profile_id)
+      .and_then(|player| player.rating);
+    let is_new_session = match sessions.last() {
+      Some(session) => game.started - session.finished > gap_secs,
+      None => true,
+    };
+    if is_new_session {
+      if let Some(session) = sessions.last_mut() {
+        session.set_rating_after(rating);
+      }
+      sessions.push(Session::new(game));
+    }
+    if let Some(session) = sessions.last_mut() {
+      session.add_game(game, tracker);
+    }
+  }
+  if let Some(session) = sessions.last_mut() {
+    session.set_rating_after(current_rating);
+  }
+  sessions.reverse();
+  return sessions;
+}
@@ -0,0 +1,164 @@
+extern crate chrono;
+
+use chrono::prelude::*;
+use chrono_tz::Tz;
+
+use crate::error::RuntimeError;
+use crate::outcome;
+
+const WEEKDAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+// hours east of UTC, e.g. "-5" or "5.5"
+pub fn parse_utc_offset(utc_offset: &str) -> Result<FixedOffset, RuntimeError> {
+  let hours: f64 = match utc_offset.parse() {
+    Ok(h) => h,
+    Err(_) => {
+      return Err(RuntimeError::new(&format!(
+        "Expected a number of hours for the utc offset, got '{}'",
+        utc_offset
+      )))
+    }
+  };
+  match FixedOffset::east_opt((hours * 3600.0).round() as i32) {
+    Some(offset) => Ok(offset),
+    None => Err(RuntimeError::new(&format!(
+      "Utc offset out of range: '{}'",
+      utc_offset
+    ))),
+  }
+}
+
+// a fixed offset is the same all year, while a named zone follows its daylight saving time changes
+#[derive(Debug, Clone, Copy)]
+pub enum Timezone {
+  Offset(FixedOffset),
+  Zone(Tz),
+}
+
+impl Timezone {
+  pub fn utc() -> Timezone {
+    Timezone::Offset(FixedOffset::east(0))
+  }
+  // the hour and the day of the week, counted from monday, at which a game started
+  fn get_local_time(&self, timestamp: i64) -> (u32, u32) {
+    let started = Utc.timestamp(timestamp, 0);
+    match self {
+      Timezone::Offset(offset) => {
+        let started = started.with_timezone(offset);
+        (started.hour(), started.weekday().num_days_from_monday())
+      }
+      Timezone::Zone(zone) => {
+        let started = started.with_timezone(zone);
+        (started.hour(), started.weekday().num_days_from_monday())
+      }
+    }
+  }
+  pub fn describe(&self) -> String {
+    match self {
+      Timezone::Offset(offset) => format!("UTC{:+.1}", offset.local_minus_utc() as f64 / 3600.0),
+      Timezone::Zone(zone) => String::from(zone.name()),
+    }
+  }
+}
+
+// an IANA zone name like "Europe/Berlin", or hours east of UTC
+pub fn parse_timezone(timezone: &str) -> Result<Timezone, RuntimeError> {
+  if timezone.parse::<f64>().is_ok() {
+    return Ok(Timezone::Offset(parse_utc_offset(timezone)?));
+  }
+  match timezone.parse::<Tz>() {
+    Ok(zone) => Ok(Timezone::Zone(zone)),
+    Err(_) => Err(RuntimeError::new(&format!(
+      "Expected a timezone like Europe/Berlin or a number of hours, got '{}'",
+      timezone
+    ))),
+  }
+}
+
+#[derive(serde::Serialize, Debug, Clone, Default)]
+pub struct TimeBucket {
+  pub label: String,
+  pub games: i32,
+  pub wins: i32,
+  pub losses: i32,
+}
+
+impl TimeBucket {
+  fn new(label: String) -> TimeBucket {
+    TimeBucket {
+      label,
+      ..TimeBucket::default()
+    }
+  }
+  fn add(&mut self, is_win: bool) {
+    self.games += 1;
+    if is_win {
+      self.wins += 1;
+    } else {
+      self.losses += 1;
+    }
+  }
+  pub fn format(&self) -> String {
+    return format!(
+      "{}: {} games, {}-{} ({:.0}%)",
+      self.label,
+      self.games,
+      self.wins,
+      self.losses,
+      self.wins as f64 / self.games as f64 * 100.0
+    );
+  }
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct TimeOfDayStats {
+  pub timezone: String,
+  // none for named zones, whose offset changes through the year
+  pub utc_offset_secs: Option<i32>,
+  pub hours: Vec<TimeBucket>,
+  pub weekdays: Vec<TimeBucket>,
+}
+
+impl TimeOfDayStats {
+  pub fn from_outcomes(
+    outcomes: &Vec<outcome::InferredOutcome>,
+    timezone: &Timezone,
+  ) -> TimeOfDayStats {
+    let mut hours: Vec<TimeBucket> = (0..24)
+      .map(|hour| TimeBucket::new(format!("{:02}:00", hour)))
+      .collect();
+    let mut weekdays: Vec<TimeBucket> = WEEKDAY_NAMES
+      .iter()
+      .map(|name| TimeBucket::new(String::from(*name)))
+      .collect();
+    for outcome in outcomes {
+      let is_win = match outcome.is_win {
+        Some(is_win) => is_win,
+        None => continue,
+      };
+      let (hour, weekday) = timezone.get_local_time(outcome.started);
+      hours[hour as usize].add(is_win);
+      weekdays[weekday as usize].add(is_win);
+    }
+    TimeOfDayStats {
+      timezone: timezone.describe(),
+      utc_offset_secs: match timezone {
+        Timezone::Offset(offset) => Some(offset.local_minus_utc()),
+        Timezone::Zone(_) => None,
+      },
+      hours,
+      weekdays,
+    }
+  }
+  pub fn format(&self) -> String {
+    let mut ret = format!("By hour ({}):\n", self.timezone);
+    for bucket in self.hours.iter().filter(|bucket| bucket.games > 0) {
+      ret += &format!("  {}\n", bucket.format());
+    }
+    ret += "By weekday:\n";
+    for bucket in self.weekdays.iter().filter(|bucket| bucket.games > 0) {
+      ret += &format!("  {}\n", bucket.format());
+    }
+    return ret;
+  }
+}
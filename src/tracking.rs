@@ -0,0 +1,98 @@
+use std::future::Future;
+
+use crate::error::RuntimeError;
+use crate::fetch;
+use crate::match_filter;
+use crate::outcome;
+use crate::player_tracker;
+use crate::server::cache;
+
+pub struct TrackedHistory {
+  // the games matching the filter, newest first
+  pub match_history: Vec<fetch::MatchHistoryGameResponse>,
+  pub tracker: player_tracker::PlayerTracker,
+  // false when the filter left out the newest game on the leaderboard, so the player's current
+  // rating is not the rating after the last game in match_history
+  pub includes_newest_game: bool,
+}
+
+// the histories come from aoe2.net directly on the command line and through the cache on the
+// server, so the fetchers are passed in
+pub async fn track_history<M, MF, R, RF>(
+  profile_id: i32,
+  leaderboard_id: fetch::LeaderboardId,
+  filter: &match_filter::MatchFilter,
+  fetch_match_history: M,
+  fetch_rating_history: R,
+) -> Result<TrackedHistory, RuntimeError>
+where
+  M: FnOnce(i32) -> MF,
+  MF: Future<Output = Result<Vec<fetch::MatchHistoryGameResponse>, RuntimeError>>,
+  R: FnOnce(i32, fetch::LeaderboardId) -> RF,
+  RF: Future<Output = Result<Vec<fetch::RatingHistoryResponse>, RuntimeError>>,
+{
+  let mut match_history = fetch_match_history(profile_id).await?;
+  match_history.retain(|mh| mh.get_leaderboard_id() == leaderboard_id);
+  if match_history.len() == 0 {
+    return Err(RuntimeError::new("Player has not played any games."));
+  }
+  if !filter.is_empty() {
+    println!("Retaining games: {}", filter.describe());
+  }
+  let matching = filter.get_matching_indices(&match_history);
+  if matching.len() == 0 {
+    return Err(RuntimeError::new("No games match the given filters."));
+  }
+
+  let rating_history = if outcome::needs_rating_history(&match_history, profile_id, filter) {
+    // the rating history is only one of the ways results are inferred
+    match fetch_rating_history(profile_id, leaderboard_id).await {
+      Ok(rating_history) => rating_history,
+      Err(e) => {
+        println!(
+          "Could not get rating history, inferring results without it: {:?}",
+          e
+        );
+        vec![]
+      }
+    }
+  } else {
+    vec![]
+  };
+
+  // outcomes are inferred from the whole history before the filter drops games
+  let mut tracker = player_tracker::PlayerTracker::new(profile_id);
+  tracker.track_players(&match_history, &rating_history, filter);
+  if let Err(e) = cache::update_player_names(&mut tracker) {
+    println!("[DB] Could not update player names: {:?}", e);
+  }
+
+  let includes_newest_game = matching.get(0) == Some(&0);
+  filter.apply(&mut match_history);
+  Ok(TrackedHistory {
+    match_history,
+    tracker,
+    includes_newest_game,
+  })
+}
+
+// fetchers for track_history that go to aoe2.net without the cache
+pub async fn fetch_match_history(
+  profile_id: i32,
+) -> Result<Vec<fetch::MatchHistoryGameResponse>, RuntimeError> {
+  match fetch::fetch_match_history_async(profile_id).await? {
+    Some(match_history) => Ok(match_history),
+    None => Err(RuntimeError::new("Could not get match history.")),
+  }
+}
+
+pub async fn fetch_rating_history(
+  profile_id: i32,
+  leaderboard_id: fetch::LeaderboardId,
+) -> Result<Vec<fetch::RatingHistoryResponse>, RuntimeError> {
+  Ok(
+    fetch::fetch_rating_history_async(profile_id, leaderboard_id)
+      .await?
+      .unwrap_or(vec![]),
+  )
+}
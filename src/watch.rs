@@ -0,0 +1,241 @@
+use crate::error::RuntimeError;
+use crate::fetch;
+use crate::match_filter;
+use crate::player_tracker;
+use crate::prediction;
+use crate::tracking;
+
+pub const DEFAULT_POLL_INTERVAL_SECS: u64 = 30;
+
+pub struct WatchOptions {
+  pub profile_id: i32,
+  pub leaderboard_id: fetch::LeaderboardId,
+  pub filter: match_filter::MatchFilter,
+  pub poll_interval_secs: u64,
+}
+
+// what changed between two polls. both games can be set when one game finished and the next one
+// started in between
+pub struct WatchUpdate {
+  pub finished: Option<fetch::MatchHistoryGameResponse>,
+  pub started: Option<fetch::MatchHistoryGameResponse>,
+  // the records including the finished game, none if nothing changed
+  pub tracker: Option<player_tracker::PlayerTracker>,
+}
+
+// follows the latest match of a player, used by watch mode and the live events on the server
+#[derive(Clone)]
+pub struct Watcher {
+  pub profile_id: i32,
+  pub leaderboard_id: fetch::LeaderboardId,
+  pub filter: match_filter::MatchFilter,
+  game_in_progress: Option<fetch::MatchHistoryGameResponse>,
+  last_started_match_id: Option<String>,
+}
+
+impl Watcher {
+  pub fn new(
+    profile_id: i32,
+    leaderboard_id: fetch::LeaderboardId,
+    filter: match_filter::MatchFilter,
+    game_in_progress: Option<fetch::MatchHistoryGameResponse>,
+  ) -> Watcher {
+    let last_started_match_id = game_in_progress
+      .as_ref()
+      .map(|game| String::from(game.get_match_id()));
+    Watcher {
+      profile_id,
+      leaderboard_id,
+      filter,
+      game_in_progress,
+      last_started_match_id,
+    }
+  }
+  fn is_finished(&self, latest: &fetch::MatchHistoryGameResponse) -> bool {
+    match &self.game_in_progress {
+      Some(game) => latest.get_match_id() != game.get_match_id() || latest.finished.is_some(),
+      None => false,
+    }
+  }
+  fn is_new_game(&self, latest: &fetch::MatchHistoryGameResponse) -> bool {
+    self.game_in_progress.is_none()
+      && latest.finished.is_none()
+      && latest.get_leaderboard_id() == self.leaderboard_id
+      && self.last_started_match_id.as_deref() != Some(latest.get_match_id())
+  }
+  // track builds the records of the player with the watcher's filter. nothing is changed when it
+  // fails, so the same game is picked up again on the next poll
+  pub async fn poll<T, TF>(&mut self, track: T) -> Result<WatchUpdate, RuntimeError>
+  where
+    T: Fn(i32, fetch::LeaderboardId, match_filter::MatchFilter) -> TF,
+    TF: std::future::Future<Output = Result<player_tracker::PlayerTracker, RuntimeError>>,
+  {
+    let mut update = WatchUpdate {
+      finished: None,
+      started: None,
+      tracker: None,
+    };
+    let latest = match fetch::fetch_latest_match_async(self.profile_id).await? {
+      Some(latest) => latest.last_match,
+      None => None,
+    };
+    let latest = match latest {
+      Some(latest) => latest,
+      None => return Ok(update),
+    };
+
+    if self.is_finished(&latest) {
+      update.tracker =
+        Some(track(self.profile_id, self.leaderboard_id, self.filter.clone()).await?);
+      update.finished = self.game_in_progress.take();
+    }
+
+    if self.is_new_game(&latest) {
+      if update.tracker.is_none() {
+        update.tracker =
+          Some(track(self.profile_id, self.leaderboard_id, self.filter.clone()).await?);
+      }
+      self.last_started_match_id = Some(String::from(latest.get_match_id()));
+      self.game_in_progress = Some(latest.clone());
+      update.started = Some(latest);
+    }
+    Ok(update)
+  }
+}
+
+async fn track(
+  profile_id: i32,
+  leaderboard_id: fetch::LeaderboardId,
+  filter: match_filter::MatchFilter,
+) -> Result<player_tracker::PlayerTracker, RuntimeError> {
+  let tracked = tracking::track_history(
+    profile_id,
+    leaderboard_id,
+    &filter,
+    tracking::fetch_match_history,
+    tracking::fetch_rating_history,
+  )
+  .await?;
+  Ok(tracked.tracker)
+}
+
+fn scouting_report(
+  game: &fetch::MatchHistoryGameResponse,
+  tracker: &player_tracker::PlayerTracker,
+) -> String {
+  let mut ret = format!(
+    "New game started: {} ({})\n",
+    game.get_match_id(),
+    game.get_format()
+  );
+  for (i, other_team) in game
+    .get_opposing_teams(tracker.profile_id)
+    .iter()
+    .enumerate()
+  {
+    ret += &format!(" Opposing team {}\n", i + 1);
+    for &enemy_player in other_team {
+      let profile_id = enemy_player.get_profile_id();
+      let (wins, losses) = tracker.get_win_loss_record(profile_id);
+      ret += &format!(
+        "  {}{} (elo {}): wins against {}, losses to {}\n",
+        enemy_player.get_name(),
+        tracker.format_aliases(profile_id),
+        enemy_player.get_rating(),
+        wins,
+        losses
+      );
+      if let Some(record) = tracker.records.get(&profile_id) {
+        if record.ratings.len() > 1 {
+          ret += &format!("    elo in past games: {}\n", record.format_ratings());
+        }
+      }
+    }
+  }
+  let my_team = game.get_my_team(tracker.profile_id);
+  if my_team.len() > 0 {
+    ret += " Teammates\n";
+    for &ally_player in &my_team {
+      let (wins, losses) = tracker.get_teammate_record(ally_player.get_profile_id());
+      ret += &format!(
+        "  {}{} (elo {}): wins with {}, losses with {}\n",
+        ally_player.get_name(),
+        tracker.format_aliases(ally_player.get_profile_id()),
+        ally_player.get_rating(),
+        wins,
+        losses
+      );
+    }
+  }
+  ret += &prediction::predict_game(game, tracker).explain();
+  return ret;
+}
+
+fn finished_report(
+  game: &fetch::MatchHistoryGameResponse,
+  tracker: &player_tracker::PlayerTracker,
+) -> String {
+  let outcome = tracker.get_outcome(game.get_match_id());
+  let mut ret = format!(
+    "Game finished: {}. Victory? {} ({})\n",
+    game.get_match_id(),
+    match outcome.and_then(|outcome| outcome.is_win) {
+      Some(is_win) => is_win.to_string(),
+      None => String::from("unknown"),
+    },
+    outcome.map_or(String::from("unknown"), |outcome| outcome.describe())
+  );
+  for enemy_player in game.get_opposing_team(tracker.profile_id) {
+    let (wins, losses) = tracker.get_win_loss_record(enemy_player.get_profile_id());
+    ret += &format!(
+      "  {} now: wins against {}, losses to {}\n",
+      enemy_player.get_name(),
+      wins,
+      losses
+    );
+  }
+  ret += &format!(
+    "Overall: {} wins, {} losses\n",
+    tracker.wins, tracker.losses
+  );
+  return ret;
+}
+
+// polls the latest match of the player, printing a scouting report when a game on the leaderboard
+// starts and the updated records once it has finished. watched is the game in progress during the
+// initial lookup, if any
+pub async fn watch(
+  options: WatchOptions,
+  watched: Option<fetch::MatchHistoryGameResponse>,
+) -> Result<(), RuntimeError> {
+  println!(
+    "Watching for new games every {}s, press ctrl-c to stop",
+    options.poll_interval_secs
+  );
+  let mut watcher = Watcher::new(
+    options.profile_id,
+    options.leaderboard_id,
+    options.filter,
+    watched,
+  );
+  loop {
+    actix_rt::time::delay_for(std::time::Duration::from_secs(options.poll_interval_secs)).await;
+
+    // a failed poll is retried on the next interval instead of ending the watch
+    let update = match watcher.poll(track).await {
+      Ok(update) => update,
+      Err(e) => {
+        println!("Could not update the watched game: {:?}", e);
+        continue;
+      }
+    };
+    if let Some(tracker) = &update.tracker {
+      if let Some(game) = &update.finished {
+        println!("{}", finished_report(game, tracker));
+      }
+      if let Some(game) = &update.started {
+        println!("{}", scouting_report(game, tracker));
+      }
+    }
+  }
+}